@@ -0,0 +1,598 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use fastwebsockets::CloseCode;
+use socket2::{Domain, Socket, Type};
+use tokio::net::{TcpListener, UdpSocket};
+
+#[cfg(feature = "tls")]
+use crate::TlsConfig;
+use crate::{
+    EchoServer,
+    limits::{ConnectionLimits, RequestLimits},
+    log_utils::HttpLogLevel,
+    raw_echo::RawLogLevel,
+    service::{
+        ChaosConfig, DeflateConfig, ShapingConfig, SseConfig, WsFragmentMode, WsSubprotocols,
+    },
+};
+#[cfg(unix)]
+use crate::{
+    socket_activation::{self, InheritedListener},
+    unix_socket::UnixSocketListener,
+};
+
+const DEFAULT_ADMIN_PREFIX: &str = "/__echo";
+const DEFAULT_MAX_RESPONSE_DELAY: Duration = Duration::from_secs(60);
+const DEFAULT_WS_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024; // 16 MB
+const DEFAULT_WS_LIMIT_CLOSE_REASON: &str = "Message too big";
+/// Max length of a close frame reason: 125 bytes of control frame payload minus 2 bytes of the code
+const MAX_WS_CLOSE_REASON_LEN: usize = 123;
+
+/// Validated options shared by all the connections of an [EchoServer]
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub http_log_level: HttpLogLevel,
+    pub raw_log_level: RawLogLevel,
+    pub ws_logging_enabled: bool,
+    pub ws_ping_interval: Option<Duration>,
+    pub ws_max_message_size: usize,
+    pub ws_max_frame_size: Option<usize>,
+    pub ws_auto_pong: bool,
+    pub ws_pong_timeout: Option<Duration>,
+    pub ws_limit_close_code: u16,
+    pub ws_limit_close_reason: String,
+    pub ws_fragment_mode: WsFragmentMode,
+    pub ws_deflate: Option<DeflateConfig>,
+    pub ws_subprotocols: WsSubprotocols,
+    pub ws_broadcast_to_sender: bool,
+    pub chaos: ChaosConfig,
+    pub shaping: ShapingConfig,
+    pub max_response_delay: Duration,
+    pub sse: SseConfig,
+    pub metrics_enabled: bool,
+    pub admin_prefix: Option<String>,
+    pub shutdown_drain_period: Duration,
+    pub limits: ConnectionLimits,
+    pub request_limits: RequestLimits,
+}
+
+/// Builder for [EchoServer].
+///
+/// Created by [EchoServer::builder]. All the options have defaults, so the simplest server is
+/// `EchoServer::builder().build().await?` which listens on a random free port of `127.0.0.1`.
+#[derive(Debug, Clone)]
+pub struct EchoServerBuilder {
+    bind_addrs: Vec<IpAddr>,
+    port: u16,
+    // Shared so the builder stays cloneable, duplicated on build
+    listeners: Vec<Arc<std::net::TcpListener>>,
+    raw_tcp_port: Option<u16>,
+    raw_udp_port: Option<u16>,
+    #[cfg(unix)]
+    unix_socket: Option<std::path::PathBuf>,
+    #[cfg(unix)]
+    unix_socket_mode: Option<u32>,
+    #[cfg(unix)]
+    unix_listeners: Vec<Arc<std::os::unix::net::UnixListener>>,
+    #[cfg(unix)]
+    socket_activation: bool,
+    config: Config,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    #[cfg(feature = "http3")]
+    http3: bool,
+}
+
+impl Default for EchoServerBuilder {
+    fn default() -> Self {
+        Self {
+            bind_addrs: Vec::new(),
+            port: 0,
+            listeners: Vec::new(),
+            raw_tcp_port: None,
+            raw_udp_port: None,
+            #[cfg(unix)]
+            unix_socket: None,
+            #[cfg(unix)]
+            unix_socket_mode: None,
+            #[cfg(unix)]
+            unix_listeners: Vec::new(),
+            #[cfg(unix)]
+            socket_activation: false,
+            config: Config {
+                http_log_level: HttpLogLevel::None,
+                raw_log_level: RawLogLevel::None,
+                ws_logging_enabled: false,
+                ws_ping_interval: None,
+                ws_max_message_size: DEFAULT_WS_MAX_MESSAGE_SIZE,
+                ws_max_frame_size: None,
+                ws_auto_pong: true,
+                ws_pong_timeout: None,
+                ws_limit_close_code: CloseCode::Size.into(),
+                ws_limit_close_reason: DEFAULT_WS_LIMIT_CLOSE_REASON.to_string(),
+                ws_fragment_mode: WsFragmentMode::default(),
+                ws_deflate: None,
+                ws_subprotocols: WsSubprotocols::default(),
+                ws_broadcast_to_sender: true,
+                chaos: ChaosConfig::default(),
+                shaping: ShapingConfig::default(),
+                max_response_delay: DEFAULT_MAX_RESPONSE_DELAY,
+                sse: SseConfig::default(),
+                metrics_enabled: false,
+                admin_prefix: Some(DEFAULT_ADMIN_PREFIX.to_string()),
+                shutdown_drain_period: Duration::ZERO,
+                limits: ConnectionLimits::default(),
+                request_limits: RequestLimits::default(),
+            },
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "http3")]
+            http3: false,
+        }
+    }
+}
+
+impl EchoServerBuilder {
+    /// Set the port to run on. Default is `0` which means a random free port will be chosen.
+    /// The port chosen for the first bind address is used for all the others.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Add an IPv4 or IPv6 address to listen on (e.g. `0.0.0.0` or `::`).
+    /// Could be called several times to listen on multiple addresses.
+    /// IPv6 addresses accept only IPv6 clients, so `0.0.0.0` and `::` could be used together.
+    /// Default is `127.0.0.1` if no address was added.
+    pub fn bind_addr(mut self, addr: IpAddr) -> Self {
+        self.bind_addrs.push(addr);
+        self
+    }
+
+    /// Serve HTTP and WebSocket on an already bound TCP listener (e.g. created by a test harness).
+    /// Could be called several times. If any listener is provided (see also [EchoServerBuilder::unix_listener]
+    /// and [EchoServerBuilder::socket_activation]) the server doesn't bind bind addresses and port by itself.
+    pub fn listener(mut self, listener: std::net::TcpListener) -> Self {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// Set the log level for http requests. Default is [HttpLogLevel::None].
+    pub fn http_log_level(mut self, http_log_level: HttpLogLevel) -> Self {
+        self.config.http_log_level = http_log_level;
+        self
+    }
+
+    /// Also echo raw TCP connections (RFC 862) on this port of the same addresses.
+    /// `Some(0)` means a random free port. Default is `None` (disabled).
+    pub fn raw_tcp_port(mut self, port: Option<u16>) -> Self {
+        self.raw_tcp_port = port;
+        self
+    }
+
+    /// Also echo UDP datagrams (RFC 862) on this port of the same addresses.
+    /// `Some(0)` means a random free port. Default is `None` (disabled).
+    pub fn raw_udp_port(mut self, port: Option<u16>) -> Self {
+        self.raw_udp_port = port;
+        self
+    }
+
+    /// Also serve HTTP and WebSocket on a Unix domain socket at this path. Default is `None`.
+    ///
+    /// A stale socket file left by a stopped server is replaced and the file is removed
+    /// when the server is dropped. Client spans show `pid` and `uid` of the peer instead of `ip`.
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, path: Option<std::path::PathBuf>) -> Self {
+        self.unix_socket = path;
+        self
+    }
+
+    /// Set permissions of the Unix domain socket file (e.g. `0o660`).
+    /// The socket is bound in a private directory next to the path and moved in place with these permissions,
+    /// so the directory must be writable. Default is `None` which means the permissions given by the umask.
+    #[cfg(unix)]
+    pub fn unix_socket_mode(mut self, mode: Option<u32>) -> Self {
+        self.unix_socket_mode = mode;
+        self
+    }
+
+    /// Serve HTTP and WebSocket on an already bound Unix domain socket listener.
+    /// Could be called several times. The socket file is not removed when the server is dropped.
+    #[cfg(unix)]
+    pub fn unix_listener(mut self, listener: std::os::unix::net::UnixListener) -> Self {
+        self.unix_listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// Serve on TCP and Unix domain sockets passed by systemd socket activation
+    /// (`LISTEN_PID` and `LISTEN_FDS` environment variables). Default is `false`.
+    ///
+    /// [EchoServerBuilder::build] fails if no sockets were passed to the process.
+    /// The sockets could be taken only once per process.
+    #[cfg(unix)]
+    pub fn socket_activation(mut self, enabled: bool) -> Self {
+        self.socket_activation = enabled;
+        self
+    }
+
+    /// Set the log level for raw TCP and UDP echo. Default is [RawLogLevel::None].
+    pub fn raw_log_level(mut self, raw_log_level: RawLogLevel) -> Self {
+        self.config.raw_log_level = raw_log_level;
+        self
+    }
+
+    /// Set whether websocket messages and events should be logged or not. Default is `false`.
+    pub fn ws_logging(mut self, enabled: bool) -> Self {
+        self.config.ws_logging_enabled = enabled;
+        self
+    }
+
+    /// Set ping interval for WebSocket connections. Default is `None`.
+    /// - `ping_interval` - duration between pings or none to disable pings
+    pub fn ws_ping_interval(mut self, ping_interval: Option<Duration>) -> Self {
+        self.config.ws_ping_interval = ping_interval;
+        self
+    }
+
+    /// Set max size of a WebSocket message in bytes. Default is 16 MB.
+    pub fn ws_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.config.ws_max_message_size = max_message_size;
+        self
+    }
+
+    /// Set max size of a WebSocket frame in bytes. Default is `None` which means the same as max message size.
+    pub fn ws_max_frame_size(mut self, max_frame_size: Option<usize>) -> Self {
+        self.config.ws_max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Set whether to answer WebSocket pings with pongs automatically. Default is `true`.
+    pub fn ws_auto_pong(mut self, enabled: bool) -> Self {
+        self.config.ws_auto_pong = enabled;
+        self
+    }
+
+    /// Set how long to wait for a pong after a ping before closing a WebSocket connection.
+    /// Default is `None` which means the ping interval.
+    pub fn ws_pong_timeout(mut self, pong_timeout: Option<Duration>) -> Self {
+        self.config.ws_pong_timeout = pong_timeout;
+        self
+    }
+
+    /// Set close code and reason sent when a WebSocket message or frame exceeds the size limit.
+    /// Default is `1009` with `Message too big` reason.
+    pub fn ws_limit_close(mut self, code: u16, reason: &str) -> Self {
+        self.config.ws_limit_close_code = code;
+        self.config.ws_limit_close_reason = reason.to_string();
+        self
+    }
+
+    /// Set how fragmented WebSocket messages are echoed. Default is [WsFragmentMode::Reassemble].
+    /// Could be overridden per connection by the `fragments` query parameter of the upgrade request.
+    pub fn ws_fragment_mode(mut self, mode: WsFragmentMode) -> Self {
+        self.config.ws_fragment_mode = mode;
+        self
+    }
+
+    /// Enable `permessage-deflate` compression of WebSocket messages. Default is `None` (disabled).
+    pub fn ws_deflate(mut self, deflate: Option<DeflateConfig>) -> Self {
+        self.config.ws_deflate = deflate;
+        self
+    }
+
+    /// Set how a WebSocket subprotocol is selected. Default is [WsSubprotocols::None].
+    pub fn ws_subprotocols(mut self, subprotocols: WsSubprotocols) -> Self {
+        self.config.ws_subprotocols = subprotocols;
+        self
+    }
+
+    /// Set whether a WebSocket session in broadcast mode receives its own messages. Default is `true`.
+    pub fn ws_broadcast_to_sender(mut self, enabled: bool) -> Self {
+        self.config.ws_broadcast_to_sender = enabled;
+        self
+    }
+
+    /// Serve HTTPS and WSS instead of plain HTTP and WebSocket. Default is `None`.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: Option<TlsConfig>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Also serve HTTP/3 over QUIC on the same addresses and ports (UDP) and advertise it
+    /// with `Alt-Svc` header of HTTP/1.1 and HTTP/2 responses. Requires [EchoServerBuilder::tls].
+    /// Default is `false`.
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, enabled: bool) -> Self {
+        self.http3 = enabled;
+        self
+    }
+
+    /// Set fault injection probabilities. Default is no random faults.
+    /// Faults requested by request headers are injected regardless of this setting.
+    pub fn chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.config.chaos = chaos;
+        self
+    }
+
+    /// Set bandwidth limit and latency of echoed bodies (and optionally WebSocket messages).
+    /// Default is no shaping. Could be overridden per request with request headers.
+    pub fn shaping(mut self, shaping: ShapingConfig) -> Self {
+        self.config.shaping = shaping;
+        self
+    }
+
+    /// Set max delay of a response a request could ask for with `x-echo-delay-ms` header or `echo_delay` query parameter.
+    /// Requests asking for a longer delay get `400 Bad Request`. Default is 60 seconds.
+    pub fn max_response_delay(mut self, max_delay: Duration) -> Self {
+        self.config.max_response_delay = max_delay;
+        self
+    }
+
+    /// Set interval and count of periodic Server-Sent Events. Default is a tick every second until the client disconnects.
+    /// Could be overridden per request with query parameters.
+    pub fn sse(mut self, sse: SseConfig) -> Self {
+        self.config.sse = sse;
+        self
+    }
+
+    /// Serve Prometheus metrics on `/metrics` instead of echoing requests to this path. Default is `false`.
+    pub fn metrics(mut self, enabled: bool) -> Self {
+        self.config.metrics_enabled = enabled;
+        self
+    }
+
+    /// Set path prefix of admin endpoints or `None` to disable them. Default is `/__echo`.
+    ///
+    /// Admin endpoints are never echoed:
+    /// - `{prefix}/healthz` - always `200 OK` while the server is running
+    /// - `{prefix}/readyz` - `200 OK` or `503 Service Unavailable` once the server is stopping
+    /// - `{prefix}/info` - JSON with version, uptime, enabled features and config of the server
+    pub fn admin_prefix(mut self, prefix: Option<String>) -> Self {
+        self.config.admin_prefix = prefix;
+        self
+    }
+
+    /// Set how long the server keeps serving after the cancellation token passed to [EchoServer::run]
+    /// is cancelled. During this period readiness endpoint fails so load balancers could drain the server.
+    /// Default is zero.
+    pub fn shutdown_drain_period(mut self, drain_period: Duration) -> Self {
+        self.config.shutdown_drain_period = drain_period;
+        self
+    }
+
+    /// Set limits of open connections and HTTP/2 streams. Default is no limits.
+    pub fn connection_limits(mut self, limits: ConnectionLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// Set max request body size and request timeouts. Default is no limits.
+    pub fn request_limits(mut self, limits: RequestLimits) -> Self {
+        self.config.request_limits = limits;
+        self
+    }
+
+    /// Validate the options and create an [EchoServer].
+    ///
+    /// Returns created [EchoServer] or an error (e.g. if some option is invalid or the provided port is already taken).
+    pub async fn build(self) -> Result<EchoServer, Error> {
+        self.validate()?;
+        #[cfg(feature = "tls")]
+        let tls_acceptor = self
+            .tls
+            .as_ref()
+            .map(TlsConfig::make_acceptor)
+            .transpose()?;
+
+        let bind_addrs = if self.bind_addrs.is_empty() {
+            vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]
+        } else {
+            self.bind_addrs
+        };
+
+        let mut listeners = Vec::with_capacity(bind_addrs.len());
+        for listener in &self.listeners {
+            listeners.push(tcp_listener_from_std(listener.try_clone()?)?);
+        }
+        #[cfg(unix)]
+        let mut unix_listeners = Vec::new();
+        #[cfg(unix)]
+        for listener in &self.unix_listeners {
+            unix_listeners.push(UnixSocketListener::from_std(listener.try_clone()?)?);
+        }
+        #[cfg(unix)]
+        if self.socket_activation {
+            for listener in socket_activation::take_listeners()? {
+                match listener {
+                    InheritedListener::Tcp(listener) => {
+                        listeners.push(tcp_listener_from_std(listener)?)
+                    }
+                    InheritedListener::Unix(listener) => {
+                        unix_listeners.push(UnixSocketListener::from_std(listener)?)
+                    }
+                }
+            }
+        }
+        #[cfg(unix)]
+        let has_listeners = !listeners.is_empty() || !unix_listeners.is_empty();
+        #[cfg(not(unix))]
+        let has_listeners = !listeners.is_empty();
+        if !has_listeners {
+            listeners = bind_all(&bind_addrs, self.port, bind_tcp, TcpListener::local_addr)?;
+        }
+        let raw_tcp_listeners = match self.raw_tcp_port {
+            Some(port) => bind_all(&bind_addrs, port, bind_tcp, TcpListener::local_addr)?,
+            None => Vec::new(),
+        };
+        let raw_udp_sockets = match self.raw_udp_port {
+            Some(port) => bind_all(
+                &bind_addrs,
+                port,
+                |addr| UdpSocket::from_std(bind_udp(addr)?),
+                UdpSocket::local_addr,
+            )?,
+            None => Vec::new(),
+        };
+        #[cfg(unix)]
+        if let Some(path) = &self.unix_socket {
+            unix_listeners.push(UnixSocketListener::bind(path, self.unix_socket_mode).await?);
+        }
+        #[cfg(feature = "http3")]
+        let quic_endpoints = match (&self.tls, self.http3) {
+            (Some(tls), true) => {
+                let server_config = tls.make_quic_config()?;
+                let mut endpoints = Vec::with_capacity(listeners.len());
+                for listener in &listeners {
+                    endpoints.push(quinn::Endpoint::new(
+                        quinn::EndpointConfig::default(),
+                        Some(server_config.clone()),
+                        bind_udp(listener.local_addr()?)?,
+                        Arc::new(quinn::TokioRuntime),
+                    )?);
+                }
+                endpoints
+            }
+            _ => Vec::new(),
+        };
+        Ok(EchoServer::new(
+            listeners,
+            raw_tcp_listeners,
+            raw_udp_sockets,
+            #[cfg(unix)]
+            unix_listeners,
+            self.config,
+            #[cfg(feature = "tls")]
+            tls_acceptor,
+            #[cfg(feature = "http3")]
+            quic_endpoints,
+        ))
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        #[cfg(feature = "http3")]
+        if self.http3 && self.tls.is_none() {
+            return Err(invalid_input("HTTP/3 requires TLS"));
+        }
+        #[cfg(unix)]
+        if self.unix_socket_mode.is_some_and(|mode| mode > 0o777) {
+            return Err(invalid_input("Unix socket mode must be at most 0o777"));
+        }
+        if self.config.ws_ping_interval == Some(Duration::ZERO) {
+            return Err(invalid_input(
+                "WebSocket ping interval must be greater than zero",
+            ));
+        }
+        if self.config.ws_max_message_size == 0 {
+            return Err(invalid_input(
+                "WebSocket max message size must be greater than zero",
+            ));
+        }
+        if self.config.ws_max_frame_size == Some(0) {
+            return Err(invalid_input(
+                "WebSocket max frame size must be greater than zero",
+            ));
+        }
+        if self.config.ws_pong_timeout == Some(Duration::ZERO) {
+            return Err(invalid_input(
+                "WebSocket pong timeout must be greater than zero",
+            ));
+        }
+        if !CloseCode::from(self.config.ws_limit_close_code).is_allowed() {
+            return Err(invalid_input("WebSocket close code is not allowed"));
+        }
+        if self.config.ws_limit_close_reason.len() > MAX_WS_CLOSE_REASON_LEN {
+            return Err(invalid_input("WebSocket close reason is too long"));
+        }
+        if let Some(prefix) = &self.config.admin_prefix
+            && (!prefix.starts_with('/') || prefix.ends_with('/'))
+        {
+            return Err(invalid_input(
+                "Admin prefix must start with '/' and must not end with '/'",
+            ));
+        }
+        self.config
+            .chaos
+            .validate()
+            .map_err(|e| invalid_input(&e))?;
+        self.config
+            .shaping
+            .validate()
+            .map_err(|e| invalid_input(&e))?;
+        self.config.sse.validate().map_err(|e| invalid_input(&e))?;
+        self.config
+            .limits
+            .validate()
+            .map_err(|e| invalid_input(&e))?;
+        self.config
+            .request_limits
+            .validate()
+            .map_err(|e| invalid_input(&e))?;
+        self.config
+            .ws_subprotocols
+            .validate()
+            .map_err(|e| invalid_input(&e))?;
+        if let Some(deflate) = &self.config.ws_deflate {
+            deflate.validate().map_err(|e| invalid_input(&e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Bind each of `ips` on `port`.
+/// Port `0` means a random free port chosen for the first address and reused for the others.
+fn bind_all<T>(
+    ips: &[IpAddr],
+    mut port: u16,
+    bind: impl Fn(SocketAddr) -> Result<T, Error>,
+    local_addr: impl Fn(&T) -> Result<SocketAddr, Error>,
+) -> Result<Vec<T>, Error> {
+    let mut sockets = Vec::with_capacity(ips.len());
+    for ip in ips {
+        let socket = bind(SocketAddr::new(*ip, port))?;
+        port = local_addr(&socket)?.port();
+        sockets.push(socket);
+    }
+    Ok(sockets)
+}
+
+fn bind_tcp(addr: SocketAddr) -> Result<TcpListener, Error> {
+    let socket = bind_socket(addr, Type::STREAM)?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+fn bind_udp(addr: SocketAddr) -> Result<std::net::UdpSocket, Error> {
+    Ok(bind_socket(addr, Type::DGRAM)?.into())
+}
+
+/// Create a non-blocking socket bound to `addr`.
+/// IPv6 sockets accept only IPv6 so `0.0.0.0` and `::` could be bound on the same port.
+fn bind_socket(addr: SocketAddr, ty: Type) -> Result<Socket, Error> {
+    let socket = Socket::new(Domain::for_address(addr), ty, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    // The same as tokio does to allow restarting the server on the same port
+    #[cfg(not(windows))]
+    if ty == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
+}
+
+fn tcp_listener_from_std(listener: std::net::TcpListener) -> Result<TcpListener, Error> {
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
+
+fn invalid_input(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
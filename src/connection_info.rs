@@ -0,0 +1,120 @@
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::{
+    fmt::{self, Display, Formatter},
+    net::{IpAddr, SocketAddr},
+};
+
+use tracing::Span;
+
+use crate::malformed_chunk::MalformedChunkTrigger;
+#[cfg(feature = "tls")]
+use crate::tls::ClientCert;
+
+/// Credentials of a process connected to a Unix domain socket
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub pid: Option<i32>,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Address of a client: IP address and port or credentials of a Unix domain socket peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAddr {
+    Ip(SocketAddr),
+    #[cfg(unix)]
+    Unix(PeerCredentials),
+}
+
+impl ClientAddr {
+    /// IP address of the client or `None` for a Unix domain socket peer
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            ClientAddr::Ip(addr) => Some(addr.ip()),
+            #[cfg(unix)]
+            ClientAddr::Unix(_) => None,
+        }
+    }
+
+    /// Process id of a Unix domain socket peer
+    pub fn pid(&self) -> Option<i32> {
+        match self {
+            ClientAddr::Ip(_) => None,
+            #[cfg(unix)]
+            ClientAddr::Unix(credentials) => credentials.pid,
+        }
+    }
+
+    /// User id of a Unix domain socket peer
+    pub fn uid(&self) -> Option<u32> {
+        match self {
+            ClientAddr::Ip(_) => None,
+            #[cfg(unix)]
+            ClientAddr::Unix(credentials) => Some(credentials.uid),
+        }
+    }
+}
+
+impl From<SocketAddr> for ClientAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ClientAddr::Ip(addr)
+    }
+}
+
+impl Display for ClientAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientAddr::Ip(addr) => write!(f, "{addr}"),
+            #[cfg(unix)]
+            ClientAddr::Unix(credentials) => match credentials.pid {
+                Some(pid) => write!(f, "unix peer pid {pid} uid {}", credentials.uid),
+                None => write!(f, "unix peer uid {}", credentials.uid),
+            },
+        }
+    }
+}
+
+/// Information about a client connection shared by all the requests of the connection
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    pub client_addr: ClientAddr,
+    pub id: u64,
+    /// Switch of the malformed chunk fault, see [crate::malformed_chunk::MalformedChunkIo]
+    pub malformed_chunk: MalformedChunkTrigger,
+    #[cfg(feature = "tls")]
+    pub client_cert: Option<Arc<ClientCert>>,
+    /// Port of the HTTP/3 endpoint to advertise to the client
+    #[cfg(feature = "http3")]
+    pub h3_port: Option<u16>,
+}
+
+impl ConnectionInfo {
+    pub fn new(client_addr: ClientAddr, id: u64) -> Self {
+        Self {
+            client_addr,
+            id,
+            malformed_chunk: MalformedChunkTrigger::default(),
+            #[cfg(feature = "tls")]
+            client_cert: None,
+            #[cfg(feature = "http3")]
+            h3_port: None,
+        }
+    }
+
+    /// Record optional fields of a client span.
+    /// The span is expected to declare all the fields as [tracing::field::Empty].
+    #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+    pub fn record_span_fields(&self, span: &Span) {
+        #[cfg(feature = "tls")]
+        if let Some(client_cert) = &self.client_cert {
+            span.record("client_cert", client_cert.subject.as_str());
+            span.record(
+                "client_cert_san",
+                client_cert.subject_alt_names.join(", ").as_str(),
+            );
+            span.record("client_cert_fingerprint", client_cert.fingerprint.as_str());
+        }
+    }
+}
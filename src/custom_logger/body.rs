@@ -38,13 +38,21 @@ where
         match this.inner.poll_frame(cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(result) => {
-                if let Some(Ok(frame)) = &result {
-                    if let Some(data) = frame.data_ref() {
-                        (this.logger)(data, this.span);
-                    }
+                if let Some(Ok(frame)) = &result
+                    && let Some(data) = frame.data_ref()
+                {
+                    (this.logger)(data, this.span);
                 }
                 Poll::Ready(result)
             }
         }
     }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
 }
@@ -1,11 +1,12 @@
-use std::{net::IpAddr, time::Instant};
+use std::time::Instant;
 
 use hyper::{
     Request, Response,
     body::{Body, Bytes},
 };
-use tracing::{Level, span};
+use tracing::{Level, field, span};
 
+use crate::connection_info::ConnectionInfo;
 use crate::log_utils::{HttpLogLevel, log_body_frame, log_headers, log_latency, log_request_uri};
 
 use super::body::LoggingBody;
@@ -17,11 +18,20 @@ pub struct Logger {
 }
 
 impl Logger {
-    pub fn new(log_level: HttpLogLevel, client_ip: IpAddr, id: u64) -> Self {
-        Self {
-            log_level,
-            span: span!(Level::INFO, "client", ip = ?client_ip, id = id),
-        }
+    pub fn new(log_level: HttpLogLevel, connection_info: &ConnectionInfo) -> Self {
+        let span = span!(
+            Level::INFO,
+            "client",
+            ip = connection_info.client_addr.ip().map(field::debug),
+            pid = connection_info.client_addr.pid(),
+            uid = connection_info.client_addr.uid(),
+            id = connection_info.id,
+            client_cert = field::Empty,
+            client_cert_san = field::Empty,
+            client_cert_fingerprint = field::Empty
+        );
+        connection_info.record_span_fields(&span);
+        Self { log_level, span }
     }
 
     pub fn wrap_request<B>(&self, request: Request<B>) -> Request<LoggingBody<B>>
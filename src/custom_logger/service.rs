@@ -1,4 +1,4 @@
-use std::{fmt::Debug, future::Future, net::IpAddr, time::Instant};
+use std::{fmt::Debug, future::Future, time::Instant};
 
 use hyper::{
     Request, Response,
@@ -7,20 +7,18 @@ use hyper::{
 use tower::{Layer, Service};
 
 use super::{body::LoggingBody, future::LoggingFuture, logger_impl::Logger};
-use crate::log_utils::HttpLogLevel;
+use crate::{connection_info::ConnectionInfo, log_utils::HttpLogLevel};
 
 pub struct LoggerLayer {
     log_level: HttpLogLevel,
-    client_addr: IpAddr,
-    id: u64,
+    connection_info: ConnectionInfo,
 }
 
 impl LoggerLayer {
-    pub fn new(log_level: HttpLogLevel, client_addr: IpAddr, id: u64) -> Self {
+    pub fn new(log_level: HttpLogLevel, connection_info: ConnectionInfo) -> Self {
         Self {
             log_level,
-            client_addr,
-            id,
+            connection_info,
         }
     }
 }
@@ -29,7 +27,7 @@ impl<S> Layer<S> for LoggerLayer {
     type Service = LoggerService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        let logger = Logger::new(self.log_level, self.client_addr, self.id);
+        let logger = Logger::new(self.log_level, &self.connection_info);
         LoggerService::new(logger, inner)
     }
 }
@@ -0,0 +1,230 @@
+use std::{
+    future::IntoFuture,
+    pin::{Pin, pin},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use h3::{
+    error::{Code, StreamError},
+    server::{RequestResolver, RequestStream},
+};
+use http_body_util::BodyExt;
+use hyper::{
+    HeaderMap, Request, Response,
+    body::{Body, Buf, Bytes, Frame},
+    header::{ALT_SVC, HeaderValue},
+};
+use quinn::{Endpoint, Incoming};
+use tokio::{select, sync::mpsc};
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_util::sync::CancellationToken;
+use tower::{Service, ServiceExt};
+use tracing::warn;
+
+use crate::{
+    config::Config, connection_info::ConnectionInfo, metrics::Metrics, service, service::WsHub,
+    tls::ClientCert,
+};
+
+/// Max number of request body chunks read ahead of the service
+const BODY_CHANNEL_CAPACITY: usize = 4;
+/// How long clients could cache the HTTP/3 advertisement
+const ALT_SVC_MAX_AGE_SECONDS: u64 = 86400;
+
+/// Advertise the HTTP/3 endpoint listening on `port` in `Alt-Svc` header
+pub(crate) fn add_alt_svc(headers: &mut HeaderMap, port: u16) {
+    let value = format!("h3=\":{port}\"; ma={ALT_SVC_MAX_AGE_SECONDS}");
+    headers.insert(ALT_SVC, HeaderValue::from_str(&value).unwrap());
+}
+
+/// Pass incoming QUIC connections of the endpoint to `sender` until cancelled
+pub(crate) async fn forward_incoming(
+    endpoint: Endpoint,
+    sender: mpsc::Sender<Incoming>,
+    cancellation_token: CancellationToken,
+) {
+    while let Some(Some(incoming)) = cancellation_token
+        .run_until_cancelled(endpoint.accept())
+        .await
+    {
+        if sender.send(incoming).await.is_err() {
+            break;
+        }
+    }
+    // Refuse new connections, the established ones are closed by their own tasks
+    endpoint.set_server_config(None);
+}
+
+pub(crate) async fn serve_connection(
+    incoming: Incoming,
+    config: Arc<Config>,
+    mut connection_info: ConnectionInfo,
+    metrics: Arc<Metrics>,
+    ws_hub: Arc<WsHub>,
+    cancellation_token: CancellationToken,
+) {
+    let connection = match cancellation_token
+        .run_until_cancelled(incoming.into_future())
+        .await
+    {
+        Some(Ok(connection)) => connection,
+        Some(Err(e)) => {
+            warn!("QUIC handshake failed: {e}");
+            return;
+        }
+        None => return,
+    };
+    connection_info.client_cert = connection
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+        .and_then(|certs| certs.first().and_then(ClientCert::from_der))
+        .map(Arc::new);
+
+    let svc = service::make_service(
+        config,
+        connection_info,
+        metrics,
+        ws_hub,
+        cancellation_token.clone(),
+    );
+    let mut h3_connection =
+        match h3::server::Connection::new(h3_quinn::Connection::new(connection)).await {
+            Ok(h3_connection) => h3_connection,
+            Err(e) => {
+                warn!("HTTP/3 connection setup failed: {e}");
+                return;
+            }
+        };
+
+    let mut shutting_down = false;
+    loop {
+        let resolver = select! {
+            resolver = h3_connection.accept() => resolver,
+            _ = cancellation_token.cancelled(), if !shutting_down => {
+                // Stop accepting new requests and wait for the requests in progress
+                shutting_down = true;
+                if h3_connection.shutdown(0).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        match resolver {
+            Ok(Some(resolver)) => {
+                tokio::spawn(serve_request(resolver, svc.clone()));
+            }
+            Ok(None) => return,
+            Err(e) => {
+                if !e.is_h3_no_error() {
+                    warn!("Error processing HTTP/3 connection: {e}");
+                }
+                return;
+            }
+        }
+    }
+}
+
+async fn serve_request<S, B>(resolver: RequestResolver<h3_quinn::Connection, Bytes>, svc: S)
+where
+    S: Service<Request<RecvBody>, Response = Response<B>>,
+    S::Error: std::fmt::Display,
+    B: Body<Data = Bytes>,
+    B::Error: std::fmt::Display,
+{
+    let (request, stream) = match resolver.resolve_request().await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            warn!("Failed to receive HTTP/3 request: {e}");
+            return;
+        }
+    };
+    let (mut send, recv) = stream.split();
+    let (sender, receiver) = mpsc::channel(BODY_CHANNEL_CAPACITY);
+    tokio::spawn(read_body(recv, sender));
+    let request = request.map(|()| RecvBody { receiver });
+
+    let response = match svc.oneshot(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Error processing HTTP/3 request: {e}");
+            send.stop_stream(Code::H3_INTERNAL_ERROR);
+            return;
+        }
+    };
+    if let Err(e) = send_response(&mut send, response).await
+        && !e.is_h3_no_error()
+    {
+        warn!("Error sending HTTP/3 response: {e}");
+    }
+}
+
+async fn send_response<B>(
+    send: &mut RequestStream<h3_quinn::SendStream<Bytes>, Bytes>,
+    response: Response<B>,
+) -> Result<(), StreamError>
+where
+    B: Body<Data = Bytes>,
+    B::Error: std::fmt::Display,
+{
+    let (parts, body) = response.into_parts();
+    send.send_response(Response::from_parts(parts, ())).await?;
+    let mut body = pin!(body);
+    while let Some(frame) = body.frame().await {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Error sending HTTP/3 response body: {e}");
+                send.stop_stream(Code::H3_INTERNAL_ERROR);
+                return Ok(());
+            }
+        };
+        match frame.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+    send.finish().await
+}
+
+/// Read the request body in a separate task, the stream itself can't be shared between threads
+async fn read_body(
+    mut recv: RequestStream<h3_quinn::RecvStream, Bytes>,
+    sender: mpsc::Sender<Result<Bytes, StreamError>>,
+) {
+    loop {
+        let chunk = match recv.recv_data().await {
+            Ok(Some(mut data)) => Ok(data.copy_to_bytes(data.remaining())),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        let is_err = chunk.is_err();
+        if sender.send(chunk).await.is_err() || is_err {
+            return;
+        }
+    }
+}
+
+/// Body of an HTTP/3 request
+pub(crate) struct RecvBody {
+    receiver: mpsc::Receiver<Result<Bytes, StreamError>>,
+}
+
+impl Body for RecvBody {
+    type Data = Bytes;
+    type Error = StreamError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.receiver
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data)))
+    }
+}
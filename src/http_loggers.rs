@@ -1,12 +1,11 @@
-use std::net::IpAddr;
-
 use hyper::{
     Request,
     body::{Body, Bytes},
 };
 use tower_http::trace::{MakeSpan, OnBodyChunk, OnRequest, OnResponse};
-use tracing::{Span, span};
+use tracing::{Span, field, span};
 
+use crate::connection_info::ConnectionInfo;
 use crate::log_utils::{HttpLogLevel, log_body_frame, log_headers, log_latency, log_request_uri};
 
 #[derive(Debug, Clone)]
@@ -86,23 +85,29 @@ impl OnBodyChunk<Bytes> for BodyLogger {
 
 #[derive(Debug, Clone)]
 pub struct SpanMaker {
-    client_ip: IpAddr,
-    id: u64,
+    connection_info: ConnectionInfo,
 }
 
 impl SpanMaker {
-    pub fn new(client_ip: IpAddr, id: u64) -> Self {
-        Self { client_ip, id }
+    pub fn new(connection_info: ConnectionInfo) -> Self {
+        Self { connection_info }
     }
 }
 
 impl<B> MakeSpan<B> for SpanMaker {
     fn make_span(&mut self, _: &Request<B>) -> Span {
-        span!(
+        let span = span!(
             tracing::Level::INFO,
             "client",
-            ip = ?self.client_ip,
-            id = self.id
-        )
+            ip = self.connection_info.client_addr.ip().map(field::debug),
+            pid = self.connection_info.client_addr.pid(),
+            uid = self.connection_info.client_addr.uid(),
+            id = self.connection_info.id,
+            client_cert = field::Empty,
+            client_cert_san = field::Empty,
+            client_cert_fingerprint = field::Empty
+        );
+        self.connection_info.record_span_fields(&span);
+        span
     }
 }
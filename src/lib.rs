@@ -3,20 +3,51 @@
 //!
 //! ## Features
 //! - Async and efficient
-//! - Always runs on ip `127.0.0.1`
+//! - Configurable bind addresses (IPv4 and IPv6), several at once
 //! - Configurable port
-//! - Supports both HTTP (versions 1 and 2) and WebSocket
+//! - Optional Unix domain socket listener (see [EchoServerBuilder::unix_socket]) logging peer `pid` and `uid` instead of IP
+//! - Serving on already bound listeners (see [EchoServer::from_listener]) or sockets passed by systemd
+//!   (see [EchoServerBuilder::socket_activation])
+//! - Connection limits (see [ConnectionLimits]): global, per client IP and HTTP/2 streams per connection
+//! - Request limits (see [RequestLimits]): max body size, header read, request and idle timeouts
+//! - Supports both HTTP (versions 1 and 2) and WebSocket, including WebSocket over HTTP/2 (RFC 8441)
+//! - Response control: status code, delay, headers and body of a response could be set by `x-echo-*` request headers or query parameters
+//! - Fault injection (see [ChaosConfig]): random or requested connection close, reset, stall, truncation or malformed chunked encoding of a response
+//! - Traffic shaping (see [ShapingConfig]): bandwidth limit and latency of echoed bodies and WebSocket messages
+//! - Prometheus metrics on `/metrics` (if enabled with [EchoServerBuilder::metrics])
+//! - Health, readiness and info endpoints under a configurable prefix (see [EchoServerBuilder::admin_prefix])
+//! - Request inspection: requests to `/inspect` are answered with a JSON document describing the request
+//! - Server-Sent Events (see [SseConfig]): requests to `/sse` or accepting `text/event-stream` get the body echoed
+//!   as events followed by periodic events, resumable with `Last-Event-ID`
 //! - Configurable HTTP log level. Could log uri, headers and body of a request.
 //! - Two implementations of http logging: a custom one and one based on [Trace](https://docs.rs/tower-http/latest/tower_http/trace/struct.Trace.html) from [tower_http](https://docs.rs/tower-http/latest/tower_http/index.html)
-//! - Logging of WebSocket messages (if enabled)
+//! - Logging of WebSocket messages (if enabled), binary messages are logged as base64
+//! - Byte-exact echo of binary WebSocket messages, text messages with invalid UTF-8 are closed with `1007`
+//! - Fragmented WebSocket messages are reassembled or echoed with the same fragmentation (see [WsFragmentMode])
+//! - Optional `permessage-deflate` WebSocket compression (see [DeflateConfig])
+//! - WebSocket subprotocol negotiation (see [WsSubprotocols])
+//! - WebSocket session modes selected by the `mode` query parameter or the `/ws/{mode}` path:
+//!   echo, broadcast to a room (see [EchoServerBuilder::ws_broadcast_to_sender]), uppercase, reverse,
+//!   periodic messages, close or drop after N messages
+//! - Configurable WebSocket message and frame size limits, pong timeout and automatic pongs
 //! - Configurable ping interval for WebSocket connections and automatic disconnection of inactive clients
+//! - Optional raw TCP and UDP echo (RFC 862) on their own ports (see [EchoServerBuilder::raw_tcp_port]
+//!   and [EchoServerBuilder::raw_udp_port]) with logging of received data (see [RawLogLevel])
 //! - Supports graceful shutdown by cancellation token
+//! - Optional TLS (HTTPS and WSS) with ALPN negotiation of HTTP/2 (`tls` feature)
+//! - Optional mutual TLS reporting the verified client certificate in response headers
+//! - Optional HTTP/3 over QUIC on the same port as TLS (`http3` feature, see [EchoServerBuilder::http3]),
+//!   advertised with `Alt-Svc` header
 //!
 //! ## Example
 //! ```no_run
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-//!   let echo_server = hyper_echo::EchoServer::new(None, hyper_echo::HttpLogLevel::None, false).await?;
+//!   let echo_server = hyper_echo::EchoServer::builder()
+//!     .port(8080)
+//!     .http_log_level(hyper_echo::HttpLogLevel::Uri)
+//!     .build()
+//!     .await?;
 //!   println!("Starting echo server on {}", echo_server.local_addr());
 //!   let cancellation_token = tokio_util::sync::CancellationToken::new();
 //!   tokio::spawn({
@@ -39,6 +70,10 @@
 //! It was created to learn how to create a custom tower layer and how to handle multiple features in one crate.
 //! But if in some case you want to use it, please don't forget to add `default-features = false` if you are using `custom_trace` because
 //! it is possible to use only one logging implementation at a time.
+//!
+//! ## TLS
+//! The `tls` feature enables HTTPS and WSS support based on [rustls](https://docs.rs/rustls/latest/rustls/).
+//! Use [EchoServerBuilder::tls] to provide a PEM certificate and key or a generated self-signed certificate.
 
 #[cfg(all(feature = "custom_trace", feature = "tower_trace"))]
 compile_error!("Please use either 'custom_trace' or 'tower_trace' feature");
@@ -49,127 +84,651 @@ mod custom_logger;
 #[cfg(feature = "tower_trace")]
 mod http_loggers;
 
+mod config;
+mod connection_info;
+#[cfg(feature = "http3")]
+mod http3;
+mod idle_timeout;
+mod limits;
 mod log_utils;
+mod malformed_chunk;
+mod metrics;
+mod raw_echo;
 mod service;
+#[cfg(unix)]
+mod socket_activation;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(unix)]
+mod unix_socket;
 mod ws_logger;
 
+pub use config::EchoServerBuilder;
+pub use limits::{ConnectionLimitMode, ConnectionLimits, RequestLimits};
 pub use log_utils::HttpLogLevel;
+pub use raw_echo::RawLogLevel;
+pub use service::{
+    ChaosConfig, DeflateConfig, ShapingConfig, SseConfig, WsFragmentMode, WsSubprotocols,
+};
+#[cfg(feature = "tls")]
+pub use tls::{ClientCertMode, TlsConfig};
+
+use config::Config;
+use connection_info::{ClientAddr, ConnectionInfo};
+use idle_timeout::{Activity, ActivityIo};
+use limits::{ConnectionLimiter, ConnectionPermit};
+use malformed_chunk::MalformedChunkIo;
+use metrics::Metrics;
+use service::WsHub;
 
-use hyper_util::rt::TokioIo;
-use std::{net::SocketAddr, pin::pin};
-use tokio::net::{TcpListener, TcpStream};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use std::{
+    net::SocketAddr,
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    task::Poll,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream, UdpSocket},
+    select,
+};
 use tokio_util::sync::CancellationToken;
-use tracing::warn;
+use tracing::{info, warn};
 
 /// Asynchronous echo server supporting HTTP and WebSocket
 pub struct EchoServer {
-    listener: TcpListener,
-    http_log_level: HttpLogLevel,
-    ws_logging_enabled: bool,
-    ws_ping_interval: Option<std::time::Duration>,
+    listeners: Vec<TcpListener>,
+    raw_tcp_listeners: Vec<TcpListener>,
+    raw_udp_sockets: Vec<Arc<UdpSocket>>,
+    #[cfg(unix)]
+    unix_listeners: Vec<unix_socket::UnixSocketListener>,
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    ws_hub: Arc<WsHub>,
+    connection_limiter: ConnectionLimiter,
+    /// Index of the listener to poll first, see [EchoServer::accept]
+    next_listener: AtomicUsize,
+    #[cfg(feature = "tls")]
+    tls_acceptor: Option<tokio_rustls::TlsAcceptor>,
+    #[cfg(feature = "http3")]
+    quic_endpoints: Vec<quinn::Endpoint>,
+}
+
+/// Connection accepted by one of the listeners
+enum Accepted {
+    Tcp(TcpStream),
+    RawTcp(TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+    #[cfg(feature = "http3")]
+    Quic(Box<quinn::Incoming>),
 }
 
 impl EchoServer {
-    /// Create a new [EchoServer] or return an error if the provided port is busy.
-    /// - `http_log_level` - the log level for http requests to use for each request
-    /// - `port` - the port to run on. If not provided a random free port will be chosen
-    /// - `ws_logging_enabled` - whether websocket messages and events should be logged or not
-    ///
-    /// Returns created [EchoServer] or an error (e.g. if the provided port is already taken).
-    pub async fn new(
-        port: Option<u16>,
-        http_log_level: HttpLogLevel,
-        ws_logging_enabled: bool,
-    ) -> Result<Self, std::io::Error> {
-        let addr = SocketAddr::from(([127, 0, 0, 1], port.unwrap_or_default()));
-
-        let listener = TcpListener::bind(addr).await?;
-        Ok(Self {
-            listener,
-            http_log_level,
-            ws_logging_enabled,
-            ws_ping_interval: None,
-        })
+    /// Create an [EchoServerBuilder] to configure and build an [EchoServer].
+    pub fn builder() -> EchoServerBuilder {
+        EchoServerBuilder::default()
+    }
+
+    /// Create an [EchoServerBuilder] serving on an already bound TCP listener instead of binding its own.
+    /// A shortcut for `EchoServer::builder().listener(listener)` (see [EchoServerBuilder::listener]).
+    pub fn from_listener(listener: std::net::TcpListener) -> EchoServerBuilder {
+        EchoServerBuilder::default().listener(listener)
+    }
+
+    pub(crate) fn new(
+        listeners: Vec<TcpListener>,
+        raw_tcp_listeners: Vec<TcpListener>,
+        raw_udp_sockets: Vec<UdpSocket>,
+        #[cfg(unix)] unix_listeners: Vec<unix_socket::UnixSocketListener>,
+        config: Config,
+        #[cfg(feature = "tls")] tls_acceptor: Option<tokio_rustls::TlsAcceptor>,
+        #[cfg(feature = "http3")] quic_endpoints: Vec<quinn::Endpoint>,
+    ) -> Self {
+        Self {
+            listeners,
+            raw_tcp_listeners,
+            raw_udp_sockets: raw_udp_sockets.into_iter().map(Arc::new).collect(),
+            #[cfg(unix)]
+            unix_listeners,
+            connection_limiter: ConnectionLimiter::new(&config.limits),
+            next_listener: AtomicUsize::new(0),
+            config: Arc::new(config),
+            metrics: Arc::new(Metrics::new()),
+            ws_hub: Arc::new(WsHub::new()),
+            #[cfg(feature = "tls")]
+            tls_acceptor,
+            #[cfg(feature = "http3")]
+            quic_endpoints,
+        }
     }
 
     /// Get [std::net::SocketAddr] of the server.
+    /// If the server listens on several addresses the first one is returned.
+    ///
+    /// Panics if the server has no TCP listeners (e.g. only Unix domain sockets were passed to it),
+    /// use [EchoServer::try_local_addr] for such servers.
     pub fn local_addr(&self) -> SocketAddr {
-        self.listener.local_addr().unwrap()
+        self.try_local_addr()
+            .expect("the server has no TCP listeners")
     }
 
-    /// Set ping interval for WebSocket connections
-    /// - `ping_interval` - duration between pings or none to disable pings
-    pub fn set_ws_ping_interval(&mut self, ping_interval: Option<std::time::Duration>) {
-        self.ws_ping_interval = ping_interval;
+    /// Get [std::net::SocketAddr] of the server or `None` if it listens only on Unix domain sockets.
+    /// If the server listens on several addresses the first one is returned.
+    pub fn try_local_addr(&self) -> Option<SocketAddr> {
+        self.listeners
+            .first()
+            .map(|listener| listener.local_addr().unwrap())
+    }
+
+    /// Get all the [std::net::SocketAddr] the server listens on.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect()
+    }
+
+    /// Get all the [std::net::SocketAddr] the raw TCP echo listens on
+    /// (see [EchoServerBuilder::raw_tcp_port]).
+    pub fn raw_tcp_addrs(&self) -> Vec<SocketAddr> {
+        self.raw_tcp_listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect()
+    }
+
+    /// Get all the [std::net::SocketAddr] the raw UDP echo listens on
+    /// (see [EchoServerBuilder::raw_udp_port]).
+    pub fn raw_udp_addrs(&self) -> Vec<SocketAddr> {
+        self.raw_udp_sockets
+            .iter()
+            .map(|socket| socket.local_addr().unwrap())
+            .collect()
+    }
+
+    /// Get the path of the Unix domain socket the server listens on
+    /// (see [EchoServerBuilder::unix_socket]).
+    /// If the server listens on several Unix domain sockets the first path is returned.
+    #[cfg(unix)]
+    pub fn unix_socket_path(&self) -> Option<&std::path::Path> {
+        self.unix_listeners
+            .iter()
+            .find_map(unix_socket::UnixSocketListener::path)
+    }
+
+    /// Get the paths of all the Unix domain sockets the server listens on.
+    /// Unnamed and abstract sockets are skipped.
+    #[cfg(unix)]
+    pub fn unix_socket_paths(&self) -> Vec<&std::path::Path> {
+        self.unix_listeners
+            .iter()
+            .filter_map(unix_socket::UnixSocketListener::path)
+            .collect()
     }
 
     /// Run the server.
-    /// - `cancellation_token` - the cancellation_token to stop the server
+    /// - `cancellation_token` - the cancellation_token to stop the server.
+    ///   The server reports not being ready once the token is cancelled. If a drain period is set
+    ///   (see [EchoServerBuilder::shutdown_drain_period]) it keeps serving for the drain period.
     ///
     /// Returns `()` or an error if something went wrong.
     pub async fn run(self, cancellation_token: CancellationToken) -> Result<(), std::io::Error> {
-        let mut connection_id = 0_u64;
+        // Shared with UDP echo which has no connections and counts datagrams instead
+        let connection_ids = Arc::new(AtomicU64::new(0));
+
+        let shutdown_token = CancellationToken::new();
+        tokio::spawn(drain_on_cancel(
+            cancellation_token,
+            shutdown_token.clone(),
+            self.metrics.clone(),
+            self.config.shutdown_drain_period,
+        ));
+        // Stops the drain task if the server fails, the caller's token is never cancelled here
+        let _shutdown_guard = shutdown_token.clone().drop_guard();
+        #[cfg(feature = "http3")]
+        let (mut quic_incoming, _quic_guard) = self.forward_quic_incoming(&shutdown_token);
+        let _udp_guard = self.spawn_udp_echo(&shutdown_token, connection_ids.clone());
 
         loop {
-            let Some(conn) = cancellation_token
-                .run_until_cancelled(self.listener.accept())
+            let Some(reserved) = shutdown_token
+                .run_until_cancelled(self.connection_limiter.reserve())
                 .await
             else {
                 break;
             };
+            #[cfg(not(feature = "http3"))]
+            let accept = self.accept();
+            #[cfg(feature = "http3")]
+            let accept = self.accept_with_quic(&mut quic_incoming);
+            let Some(conn) = shutdown_token.run_until_cancelled(accept).await else {
+                break;
+            };
 
-            let (stream, client_addr) = conn?;
-            self.process_connection(
-                stream,
-                client_addr,
-                connection_id,
-                cancellation_token.clone(),
-            );
-            connection_id += 1;
+            let (accepted, client_addr) = conn?;
+            let permit = match self.connection_limiter.admit(client_addr.ip(), reserved) {
+                Ok(permit) => permit,
+                Err(e) => {
+                    self.metrics.record_rejected_connection();
+                    warn!("Rejected connection from {client_addr}: {e}");
+                    continue;
+                }
+            };
+            let connection_id = connection_ids.fetch_add(1, Ordering::Relaxed);
+            match accepted {
+                Accepted::Tcp(stream) => self.process_connection(
+                    stream,
+                    client_addr,
+                    connection_id,
+                    permit,
+                    shutdown_token.clone(),
+                ),
+                Accepted::RawTcp(stream) => self.process_raw_tcp_connection(
+                    stream,
+                    client_addr,
+                    connection_id,
+                    permit,
+                    shutdown_token.clone(),
+                ),
+                #[cfg(unix)]
+                Accepted::Unix(stream) => self.process_unix_connection(
+                    stream,
+                    client_addr,
+                    connection_id,
+                    permit,
+                    shutdown_token.clone(),
+                ),
+                #[cfg(feature = "http3")]
+                Accepted::Quic(incoming) => self.process_quic_connection(
+                    incoming,
+                    client_addr,
+                    connection_id,
+                    permit,
+                    shutdown_token.clone(),
+                ),
+            }
         }
         Ok(())
     }
 
+    /// Accept a connection from any of the listeners.
+    /// Polling starts after the listener accepted the last time so a busy listener can't starve the others.
+    async fn accept(&self) -> Result<(Accepted, ClientAddr), std::io::Error> {
+        std::future::poll_fn(|cx| {
+            let count = self.listeners_count();
+            let start = self.next_listener.load(Ordering::Relaxed);
+            for i in 0..count {
+                let index = (start + i) % count;
+                if let Poll::Ready(conn) = self.poll_accept(index, cx) {
+                    self.next_listener.store(index + 1, Ordering::Relaxed);
+                    return Poll::Ready(conn);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    fn listeners_count(&self) -> usize {
+        let count = self.listeners.len() + self.raw_tcp_listeners.len();
+        #[cfg(unix)]
+        let count = count + self.unix_listeners.len();
+        count
+    }
+
+    /// Poll the listener with `index` counting HTTP, raw TCP and Unix domain socket listeners in this order
+    fn poll_accept(
+        &self,
+        index: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<(Accepted, ClientAddr), std::io::Error>> {
+        if let Some(listener) = self.listeners.get(index) {
+            return listener
+                .poll_accept(cx)
+                .map_ok(|(stream, addr)| (Accepted::Tcp(stream), addr.into()));
+        }
+        let index = index - self.listeners.len();
+        if let Some(listener) = self.raw_tcp_listeners.get(index) {
+            return listener
+                .poll_accept(cx)
+                .map_ok(|(stream, addr)| (Accepted::RawTcp(stream), addr.into()));
+        }
+        #[cfg(unix)]
+        if let Some(listener) = self
+            .unix_listeners
+            .get(index - self.raw_tcp_listeners.len())
+        {
+            return listener.poll_accept(cx).map_ok(|(stream, credentials)| {
+                (Accepted::Unix(stream), ClientAddr::Unix(credentials))
+            });
+        }
+        Poll::Pending
+    }
+
+    /// Accept a TCP connection or a QUIC connection forwarded by [http3::forward_incoming]
+    #[cfg(feature = "http3")]
+    async fn accept_with_quic(
+        &self,
+        quic_incoming: &mut tokio::sync::mpsc::Receiver<quinn::Incoming>,
+    ) -> Result<(Accepted, ClientAddr), std::io::Error> {
+        select! {
+            conn = self.accept() => conn,
+            Some(incoming) = quic_incoming.recv() => {
+                let client_addr = incoming.remote_address().into();
+                Ok((Accepted::Quic(Box::new(incoming)), client_addr))
+            }
+        }
+    }
+
+    /// Start accepting QUIC connections of all the endpoints.
+    /// The endpoints stop accepting when the returned guard is dropped.
+    #[cfg(feature = "http3")]
+    fn forward_quic_incoming(
+        &self,
+        cancellation_token: &CancellationToken,
+    ) -> (
+        tokio::sync::mpsc::Receiver<quinn::Incoming>,
+        tokio_util::sync::DropGuard,
+    ) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let token = cancellation_token.child_token();
+        for endpoint in &self.quic_endpoints {
+            tokio::spawn(http3::forward_incoming(
+                endpoint.clone(),
+                sender.clone(),
+                token.clone(),
+            ));
+        }
+        (receiver, token.drop_guard())
+    }
+
+    /// Start echoing datagrams of all the raw UDP sockets.
+    /// The sockets stop echoing when the returned guard is dropped.
+    fn spawn_udp_echo(
+        &self,
+        cancellation_token: &CancellationToken,
+        connection_ids: Arc<AtomicU64>,
+    ) -> tokio_util::sync::DropGuard {
+        let token = cancellation_token.child_token();
+        for socket in &self.raw_udp_sockets {
+            tokio::spawn(raw_echo::echo_udp(
+                socket.clone(),
+                self.config.raw_log_level,
+                connection_ids.clone(),
+                token.clone(),
+            ));
+        }
+        token.drop_guard()
+    }
+
+    fn process_raw_tcp_connection(
+        &self,
+        stream: TcpStream,
+        client_addr: ClientAddr,
+        id: u64,
+        permit: ConnectionPermit,
+        cancellation_token: CancellationToken,
+    ) {
+        let log_level = self.config.raw_log_level;
+        let metrics = self.metrics.clone();
+
+        tokio::task::spawn(async move {
+            let _permit = permit;
+            let _active_connection = metrics.connection_started();
+            let connection_info = ConnectionInfo::new(client_addr, id);
+            raw_echo::echo_tcp(stream, log_level, connection_info, cancellation_token).await;
+        });
+    }
+
+    #[cfg(unix)]
+    fn process_unix_connection(
+        &self,
+        stream: tokio::net::UnixStream,
+        client_addr: ClientAddr,
+        id: u64,
+        permit: ConnectionPermit,
+        cancellation_token: CancellationToken,
+    ) {
+        let config = self.config.clone();
+        let metrics = self.metrics.clone();
+        let ws_hub = self.ws_hub.clone();
+
+        tokio::task::spawn(async move {
+            let _permit = permit;
+            let _active_connection = metrics.connection_started();
+            let connection_info = ConnectionInfo::new(client_addr, id);
+            serve_connection(
+                stream,
+                config,
+                connection_info,
+                metrics.clone(),
+                ws_hub,
+                false,
+                cancellation_token,
+            )
+            .await;
+        });
+    }
+
     fn process_connection(
         &self,
         stream: TcpStream,
-        client_addr: SocketAddr,
+        client_addr: ClientAddr,
         id: u64,
+        permit: ConnectionPermit,
         cancellation_token: CancellationToken,
     ) {
-        let io = TokioIo::new(stream);
-        let svc = service::make_service(
-            self.http_log_level,
-            self.ws_logging_enabled,
-            self.ws_ping_interval,
-            client_addr.ip(),
-            id,
-            cancellation_token.clone(),
-        );
+        let config = self.config.clone();
+        let metrics = self.metrics.clone();
+        let ws_hub = self.ws_hub.clone();
+        #[cfg(feature = "tls")]
+        let tls_acceptor = self.tls_acceptor.clone();
+        #[cfg(feature = "http3")]
+        let h3_port = (!self.quic_endpoints.is_empty())
+            .then(|| stream.local_addr().ok().map(|addr| addr.port()))
+            .flatten();
 
         tokio::task::spawn(async move {
-            let executor = hyper_util::rt::TokioExecutor::new();
-            let builder = hyper_util::server::conn::auto::Builder::new(executor);
-            let connection = builder.serve_connection_with_upgrades(
-                io,
-                hyper_util::service::TowerToHyperService::new(svc),
-            );
-            let mut connection = pin!(connection);
-
-            match cancellation_token
-                .run_until_cancelled(connection.as_mut())
-                .await
+            let _permit = permit;
+            let _active_connection = metrics.connection_started();
+            #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+            let mut connection_info = ConnectionInfo::new(client_addr, id);
+            #[cfg(feature = "http3")]
             {
-                Some(res) => {
-                    if let Err(e) = res {
-                        warn!("Error processing connection: {e}");
+                connection_info.h3_port = h3_port;
+            }
+
+            #[cfg(feature = "tls")]
+            if let Some(tls_acceptor) = tls_acceptor {
+                let Some(accepted) = cancellation_token
+                    .run_until_cancelled(tls_acceptor.accept(stream))
+                    .await
+                else {
+                    return;
+                };
+                match accepted {
+                    Ok(stream) => {
+                        let tls_connection = stream.get_ref().1;
+                        let http2_only = tls::is_h2_negotiated(tls_connection.alpn_protocol());
+                        connection_info.client_cert = tls_connection
+                            .peer_certificates()
+                            .and_then(|certs| certs.first())
+                            .and_then(tls::ClientCert::from_der)
+                            .map(Arc::new);
+                        serve_connection(
+                            stream,
+                            config,
+                            connection_info,
+                            metrics.clone(),
+                            ws_hub,
+                            http2_only,
+                            cancellation_token,
+                        )
+                        .await;
                     }
+                    Err(e) => warn!("TLS handshake failed: {e}"),
                 }
-                None => {
-                    connection.as_mut().graceful_shutdown();
-                    let _ = connection.await;
-                }
+                return;
             }
+
+            serve_connection(
+                stream,
+                config,
+                connection_info,
+                metrics.clone(),
+                ws_hub,
+                false,
+                cancellation_token,
+            )
+            .await;
         });
     }
+
+    #[cfg(feature = "http3")]
+    fn process_quic_connection(
+        &self,
+        incoming: Box<quinn::Incoming>,
+        client_addr: ClientAddr,
+        id: u64,
+        permit: ConnectionPermit,
+        cancellation_token: CancellationToken,
+    ) {
+        let config = self.config.clone();
+        let metrics = self.metrics.clone();
+        let ws_hub = self.ws_hub.clone();
+
+        tokio::task::spawn(async move {
+            let _permit = permit;
+            let _active_connection = metrics.connection_started();
+            let connection_info = ConnectionInfo::new(client_addr, id);
+            http3::serve_connection(
+                *incoming,
+                config,
+                connection_info,
+                metrics.clone(),
+                ws_hub,
+                cancellation_token,
+            )
+            .await;
+        });
+    }
+}
+
+/// Mark the server as not ready once `cancellation_token` is cancelled
+/// and stop it with `shutdown_token` after `drain_period`
+async fn drain_on_cancel(
+    cancellation_token: CancellationToken,
+    shutdown_token: CancellationToken,
+    metrics: Arc<Metrics>,
+    drain_period: Duration,
+) {
+    if shutdown_token
+        .run_until_cancelled(cancellation_token.cancelled())
+        .await
+        .is_none()
+    {
+        return;
+    }
+    metrics.set_ready(false);
+    if !drain_period.is_zero() {
+        shutdown_token
+            .run_until_cancelled(tokio::time::sleep(drain_period))
+            .await;
+    }
+    shutdown_token.cancel();
+}
+
+async fn serve_connection<S>(
+    stream: S,
+    config: Arc<Config>,
+    connection_info: ConnectionInfo,
+    metrics: Arc<Metrics>,
+    ws_hub: Arc<WsHub>,
+    http2_only: bool,
+    cancellation_token: CancellationToken,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let activity = Activity::new();
+    let io = MalformedChunkIo::new(
+        ActivityIo::new(stream, activity.clone()),
+        connection_info.malformed_chunk.clone(),
+    );
+    let io = TokioIo::new(io);
+    let max_concurrent_streams = config.limits.max_concurrent_streams;
+    let request_limits = config.request_limits.clone();
+    let svc = service::make_service(
+        config,
+        connection_info,
+        metrics,
+        ws_hub,
+        cancellation_token.clone(),
+    );
+
+    let executor = hyper_util::rt::TokioExecutor::new();
+    let mut builder = hyper_util::server::conn::auto::Builder::new(executor);
+    // Advertise SETTINGS_ENABLE_CONNECT_PROTOCOL so clients can open WebSockets over HTTP/2
+    builder.http2().enable_connect_protocol();
+    if let Some(max_concurrent_streams) = max_concurrent_streams {
+        builder
+            .http2()
+            .max_concurrent_streams(max_concurrent_streams);
+    }
+    if let Some(header_read_timeout) = request_limits.header_read_timeout {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(header_read_timeout);
+    }
+    if http2_only {
+        builder = builder.http2_only();
+    }
+    let connection = builder
+        .serve_connection_with_upgrades(io, hyper_util::service::TowerToHyperService::new(svc));
+    let mut connection = pin!(connection);
+
+    let idle = async {
+        match request_limits.idle_timeout {
+            Some(idle_timeout) => activity.wait_idle(idle_timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    select! {
+        res = connection.as_mut() => {
+            if let Err(e) = res {
+                warn!("Error processing connection: {e}");
+            }
+            return;
+        }
+        _ = cancellation_token.cancelled() => {}
+        _ = idle => {
+            info!("Closing idle connection");
+        }
+    }
+    connection.as_mut().graceful_shutdown();
+    let _ = connection.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_util::sync::CancellationToken;
+
+    use super::EchoServer;
+
+    #[tokio::test]
+    async fn readiness_fails_on_cancel_without_drain() {
+        let echo_server = EchoServer::builder().build().await.unwrap();
+        let metrics = echo_server.metrics.clone();
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+
+        echo_server.run(cancellation_token).await.unwrap();
+        assert!(!metrics.is_ready());
+    }
 }
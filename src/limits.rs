@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// What to do with a new connection when [ConnectionLimits::max_connections] is reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionLimitMode {
+    /// Stop accepting connections until some connection is closed
+    #[default]
+    Backpressure,
+    /// Accept and immediately close new connections
+    Reject,
+}
+
+/// Connection limits of an [EchoServer](crate::EchoServer). All limits are disabled by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Max number of simultaneously open connections
+    pub max_connections: Option<usize>,
+    /// Behavior when `max_connections` is reached
+    pub mode: ConnectionLimitMode,
+    /// Max number of simultaneously open connections from one client IP address.
+    /// Connections over the limit are closed right after accepting.
+    pub max_connections_per_ip: Option<usize>,
+    /// Max number of concurrent HTTP/2 streams of a connection
+    pub max_concurrent_streams: Option<u32>,
+}
+
+impl ConnectionLimits {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.max_connections == Some(0) {
+            return Err("Max connections must be greater than zero".to_string());
+        }
+        if self.max_connections_per_ip == Some(0) {
+            return Err("Max connections per IP must be greater than zero".to_string());
+        }
+        if self.max_concurrent_streams == Some(0) {
+            return Err("Max concurrent streams must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Request size and time limits of an [EchoServer](crate::EchoServer). All limits are disabled by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Max size of a request body in bytes.
+    /// Requests with a larger `content-length` get `413 Payload Too Large`.
+    /// Bodies without `content-length` (e.g. chunked) are checked while they are streamed,
+    /// so the request is aborted without a response once the limit is exceeded.
+    pub max_body_size: Option<usize>,
+    /// Max time to receive request headers (HTTP/1 only). Also closes idle HTTP/1 keep-alive connections.
+    pub header_read_timeout: Option<Duration>,
+    /// Max time to receive a request and produce response headers.
+    /// Responded with `408 Request Timeout` if the request body is not received in time
+    /// or with `504 Gateway Timeout` if the response is not ready in time.
+    pub request_timeout: Option<Duration>,
+    /// Close a connection after no data was sent or received for this time
+    pub idle_timeout: Option<Duration>,
+}
+
+impl RequestLimits {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let timeouts = [
+            ("Header read timeout", self.header_read_timeout),
+            ("Request timeout", self.request_timeout),
+            ("Idle timeout", self.idle_timeout),
+        ];
+        for (name, timeout) in timeouts {
+            if timeout == Some(Duration::ZERO) {
+                return Err(format!("{name} must be greater than zero"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of open connections according to [ConnectionLimits]
+#[derive(Debug)]
+pub(crate) struct ConnectionLimiter {
+    connections: Option<Arc<Semaphore>>,
+    mode: ConnectionLimitMode,
+    max_connections_per_ip: Option<usize>,
+    connections_per_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(limits: &ConnectionLimits) -> Self {
+        Self {
+            connections: limits
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            mode: limits.mode,
+            max_connections_per_ip: limits.max_connections_per_ip,
+            connections_per_ip: Arc::default(),
+        }
+    }
+
+    /// Wait until a new connection could be accepted in [ConnectionLimitMode::Backpressure] mode.
+    /// Returns immediately in other cases.
+    pub async fn reserve(&self) -> Option<OwnedSemaphorePermit> {
+        match (&self.connections, self.mode) {
+            (Some(connections), ConnectionLimitMode::Backpressure) => {
+                connections.clone().acquire_owned().await.ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Check the limits for an accepted connection.
+    /// - `client_ip` - IP address of the client or `None` if it has no IP (no per IP limit then)
+    /// - `reserved` - the permit returned by [ConnectionLimiter::reserve]
+    ///
+    /// Returns a permit to hold while the connection is open or an error message if the connection must be closed.
+    pub fn admit(
+        &self,
+        client_ip: Option<IpAddr>,
+        reserved: Option<OwnedSemaphorePermit>,
+    ) -> Result<ConnectionPermit, String> {
+        let connection = match (&self.connections, reserved) {
+            (_, Some(reserved)) => Some(reserved),
+            (Some(connections), None) => Some(
+                connections
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| "Max number of connections reached".to_string())?,
+            ),
+            (None, None) => None,
+        };
+
+        let client_ip = match (self.max_connections_per_ip, client_ip) {
+            (Some(max), Some(client_ip)) => {
+                let mut connections_per_ip = self.connections_per_ip.lock().unwrap();
+                let count = connections_per_ip.entry(client_ip).or_default();
+                if *count >= max {
+                    return Err(format!(
+                        "Max number of connections from {client_ip} reached"
+                    ));
+                }
+                *count += 1;
+                Some(client_ip)
+            }
+            _ => None,
+        };
+
+        Ok(ConnectionPermit {
+            _connection: connection,
+            client_ip,
+            connections_per_ip: self.connections_per_ip.clone(),
+        })
+    }
+}
+
+/// Releases the connection slots on drop
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    _connection: Option<OwnedSemaphorePermit>,
+    client_ip: Option<IpAddr>,
+    connections_per_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let Some(client_ip) = self.client_ip else {
+            return;
+        };
+        let mut connections_per_ip = self.connections_per_ip.lock().unwrap();
+        if let Some(count) = connections_per_ip.get_mut(&client_ip) {
+            *count -= 1;
+            if *count == 0 {
+                connections_per_ip.remove(&client_ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::{ConnectionLimitMode, ConnectionLimiter, ConnectionLimits};
+
+    #[test]
+    fn limits_are_enforced() {
+        let limiter = ConnectionLimiter::new(&ConnectionLimits {
+            max_connections: Some(2),
+            mode: ConnectionLimitMode::Reject,
+            max_connections_per_ip: Some(1),
+            ..Default::default()
+        });
+        let first_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let second_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        let third_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3));
+
+        let first = limiter.admit(Some(first_ip), None).unwrap();
+        assert!(limiter.admit(Some(first_ip), None).is_err());
+        let _second = limiter.admit(Some(second_ip), None).unwrap();
+        assert!(limiter.admit(Some(third_ip), None).is_err());
+
+        drop(first);
+        assert!(limiter.admit(Some(first_ip), None).is_ok());
+    }
+}
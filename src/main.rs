@@ -1,11 +1,15 @@
-use std::{io::IsTerminal, process::exit};
+use std::{io::IsTerminal, net::IpAddr, process::exit, time::Duration};
 
 use clap::Parser;
 use tokio::{select, signal::ctrl_c};
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, info};
 
-use hyper_echo::EchoServer;
+use hyper_echo::{
+    ChaosConfig, ConnectionLimitMode, ConnectionLimits, DeflateConfig, EchoServer,
+    EchoServerBuilder, RawLogLevel, RequestLimits, ShapingConfig, SseConfig, WsFragmentMode,
+    WsSubprotocols,
+};
 
 #[derive(Debug, Parser)]
 #[command(about = "A simple echo server with http and websocket support")]
@@ -18,7 +22,19 @@ struct Args {
     #[arg(short('w'), long, action)]
     log_ws: bool,
 
-    /// Verbose logging. A shortcut for --http_log_level=3 --log_ws
+    /// Port for raw TCP echo (disabled if not provided, 0 for a random free port)
+    #[arg(long)]
+    raw_tcp_port: Option<u16>,
+
+    /// Port for raw UDP echo (disabled if not provided, 0 for a random free port)
+    #[arg(long)]
+    raw_udp_port: Option<u16>,
+
+    /// Set log level for raw TCP and UDP echo: 0 - no logging, 1 - log connections and sizes of received data, 2 - log received data as well
+    #[arg(long, default_value = "0", value_parser = clap::value_parser!(u8).range(0..=2))]
+    raw_log_level: u8,
+
+    /// Verbose logging. A shortcut for --http_log_level=3 --log_ws --raw-log-level=2
     #[arg(short, long, action)]
     verbose: bool,
 
@@ -30,6 +46,31 @@ struct Args {
     #[arg(short, long)]
     port: Option<u16>,
 
+    /// IP addresses to listen on, e.g. 0.0.0.0 or :: (could be repeated or comma separated)
+    #[arg(
+        short,
+        long,
+        alias = "host",
+        default_value = "127.0.0.1",
+        value_delimiter = ','
+    )]
+    bind: Vec<IpAddr>,
+
+    /// Path of a Unix domain socket to serve HTTP and websocket on as well
+    #[cfg(unix)]
+    #[arg(long)]
+    unix_socket: Option<std::path::PathBuf>,
+
+    /// Permissions of the Unix domain socket file in octal, e.g. 660
+    #[cfg(unix)]
+    #[arg(long, requires = "unix_socket", value_parser = parse_octal)]
+    unix_socket_mode: Option<u32>,
+
+    /// Serve on sockets passed by systemd socket activation (LISTEN_FDS) instead of binding --bind and --port
+    #[cfg(unix)]
+    #[arg(long, action, conflicts_with = "port")]
+    socket_activation: bool,
+
     /// Websocket ping interval in milliseconds
     #[arg(short('i'), long, default_value = "5000")]
     ws_ping_interval: Option<u64>,
@@ -37,6 +78,197 @@ struct Args {
     /// Disable websocket ping
     #[arg(short('d'), long, action, conflicts_with = "ws_ping_interval")]
     disable_websocket_ping: bool,
+
+    /// Max size of a websocket message in bytes
+    #[arg(short('m'), long, default_value = "16777216")]
+    ws_max_message_size: usize,
+
+    /// Max size of a websocket frame in bytes (same as max message size by default)
+    #[arg(long)]
+    ws_max_frame_size: Option<usize>,
+
+    /// Don't answer websocket pings automatically
+    #[arg(long, action)]
+    ws_disable_auto_pong: bool,
+
+    /// Time in milliseconds to wait for a pong before closing a websocket connection (ping interval by default)
+    #[arg(long)]
+    ws_pong_timeout: Option<u64>,
+
+    /// Close code sent when a websocket message or frame is too large
+    #[arg(long, default_value = "1009")]
+    ws_limit_close_code: u16,
+
+    /// Close reason sent when a websocket message or frame is too large
+    #[arg(long, default_value = "Message too big")]
+    ws_limit_close_reason: String,
+
+    /// How fragmented websocket messages are echoed: reassemble or mirror
+    #[arg(long, default_value = "reassemble")]
+    ws_fragment_mode: WsFragmentMode,
+
+    /// Websocket subprotocol supported by the server. Could be repeated.
+    /// The first one offered by a client is selected.
+    #[arg(long = "ws-subprotocol", conflicts_with = "ws_echo_subprotocol")]
+    ws_subprotocols: Vec<String>,
+
+    /// Select the first websocket subprotocol offered by a client, whatever it is
+    #[arg(long, action)]
+    ws_echo_subprotocol: bool,
+
+    /// Don't send websocket messages in broadcast mode back to the sender
+    #[arg(long, action)]
+    ws_broadcast_skip_sender: bool,
+
+    /// Enable permessage-deflate compression of websocket messages
+    #[arg(long, action)]
+    ws_deflate: bool,
+
+    /// Max window bits (8-15) used by the server to compress websocket messages
+    #[arg(long, default_value = "15")]
+    ws_deflate_server_max_window_bits: u8,
+
+    /// Max window bits (8-15) the client has to use to compress websocket messages
+    #[arg(long)]
+    ws_deflate_client_max_window_bits: Option<u8>,
+
+    /// Reset the server compression context after every websocket message
+    #[arg(long, action)]
+    ws_deflate_server_no_context_takeover: bool,
+
+    /// Ask clients to reset their compression context after every websocket message
+    #[arg(long, action)]
+    ws_deflate_client_no_context_takeover: bool,
+
+    /// Probability (from 0 to 1) to close a connection before sending response headers
+    #[arg(long, default_value = "0")]
+    fault_close: f64,
+
+    /// Probability (from 0 to 1) to abort a connection in the middle of a response body
+    #[arg(long, default_value = "0")]
+    fault_reset: f64,
+
+    /// Probability (from 0 to 1) to stop sending a response body without closing the connection
+    #[arg(long, default_value = "0")]
+    fault_stall: f64,
+
+    /// Probability (from 0 to 1) to end a response body prematurely
+    #[arg(long, default_value = "0")]
+    fault_truncate: f64,
+
+    /// Probability (from 0 to 1) to send a chunk with invalid size in an HTTP/1.1 response body and close the connection
+    #[arg(long, default_value = "0")]
+    fault_malformed_chunk: f64,
+
+    /// Number of response body bytes to send before a reset, stall, truncate or malformed chunk fault
+    #[arg(long, default_value = "0")]
+    fault_after_bytes: usize,
+
+    /// Bandwidth limit of echoed bodies in bytes per second
+    #[arg(long)]
+    rate_limit: Option<u64>,
+
+    /// Latency in milliseconds added to each chunk of an echoed body
+    #[arg(long, default_value = "0")]
+    chunk_latency: u64,
+
+    /// Max random latency in milliseconds added to each chunk of an echoed body
+    #[arg(long, default_value = "0")]
+    latency_jitter: u64,
+
+    /// Apply bandwidth limit and latency to echoed websocket messages as well
+    #[arg(long, action)]
+    shape_ws: bool,
+
+    /// Max delay in milliseconds a request could ask for with x-echo-delay-ms header or echo_delay query parameter
+    #[arg(long, default_value = "60000")]
+    max_response_delay: u64,
+
+    /// Interval in milliseconds between periodic server-sent events
+    #[arg(long, default_value = "1000")]
+    sse_interval: u64,
+
+    /// Number of periodic server-sent events after which the stream ends (unlimited by default)
+    #[arg(long)]
+    sse_count: Option<u64>,
+
+    /// Serve Prometheus metrics on /metrics
+    #[arg(long, action)]
+    metrics: bool,
+
+    /// Path prefix of health, readiness and info endpoints
+    #[arg(long, default_value = "/__echo")]
+    admin_prefix: String,
+
+    /// Disable health, readiness and info endpoints
+    #[arg(long, action, conflicts_with = "admin_prefix")]
+    disable_admin: bool,
+
+    /// Time in milliseconds to keep serving with failing readiness after a shutdown signal
+    #[arg(long, default_value = "0")]
+    shutdown_drain_period: u64,
+
+    /// Max number of simultaneously open connections
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// Close new connections over --max-connections instead of waiting for a free slot
+    #[arg(long, action, requires = "max_connections")]
+    reject_over_limit: bool,
+
+    /// Max number of simultaneously open connections from one client IP
+    #[arg(long)]
+    max_connections_per_ip: Option<usize>,
+
+    /// Max number of concurrent HTTP/2 streams of a connection
+    #[arg(long)]
+    max_concurrent_streams: Option<u32>,
+
+    /// Max size of a request body in bytes
+    #[arg(long)]
+    max_body_size: Option<usize>,
+
+    /// Max time in milliseconds to receive request headers (HTTP/1 only)
+    #[arg(long)]
+    header_read_timeout: Option<u64>,
+
+    /// Max time in milliseconds to receive a request and produce response headers
+    #[arg(long)]
+    request_timeout: Option<u64>,
+
+    /// Close connections without any traffic for this number of milliseconds
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+
+    /// Path to a PEM certificate chain to serve HTTPS and WSS with
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<std::path::PathBuf>,
+
+    /// Path to a PEM private key of the certificate provided with --tls-cert
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<std::path::PathBuf>,
+
+    /// Serve HTTPS and WSS with a generated self-signed certificate for localhost
+    #[cfg(feature = "tls")]
+    #[arg(long, action, conflicts_with = "tls_cert")]
+    tls_self_signed: bool,
+
+    /// Path to a PEM CA bundle to verify client certificates with (enables mutual TLS)
+    #[cfg(feature = "tls")]
+    #[arg(long)]
+    tls_client_ca: Option<std::path::PathBuf>,
+
+    /// Reject clients without a certificate (by default a client certificate is optional)
+    #[cfg(feature = "tls")]
+    #[arg(long, action, requires = "tls_client_ca")]
+    tls_client_cert_required: bool,
+
+    /// Serve HTTP/3 over QUIC on the same port (UDP) and advertise it with Alt-Svc. Requires TLS.
+    #[cfg(feature = "http3")]
+    #[arg(long, action)]
+    http3: bool,
 }
 
 impl Args {
@@ -45,12 +277,145 @@ impl Args {
         if args.verbose {
             args.log_ws = true;
             args.http_log_level = 3;
+            args.raw_log_level = 2;
         }
         if args.disable_websocket_ping {
             args.ws_ping_interval = None;
         }
         args
     }
+
+    fn to_builder(&self) -> Result<EchoServerBuilder, std::io::Error> {
+        let mut builder = EchoServer::builder()
+            .port(self.port.unwrap_or_default())
+            .http_log_level(self.http_log_level.into())
+            .raw_tcp_port(self.raw_tcp_port)
+            .raw_udp_port(self.raw_udp_port)
+            .raw_log_level(
+                RawLogLevel::try_from(self.raw_log_level)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+            )
+            .ws_logging(self.log_ws)
+            .ws_ping_interval(self.ws_ping_interval.map(Duration::from_millis))
+            .ws_max_message_size(self.ws_max_message_size)
+            .ws_max_frame_size(self.ws_max_frame_size)
+            .ws_auto_pong(!self.ws_disable_auto_pong)
+            .ws_pong_timeout(self.ws_pong_timeout.map(Duration::from_millis))
+            .ws_limit_close(self.ws_limit_close_code, &self.ws_limit_close_reason)
+            .ws_fragment_mode(self.ws_fragment_mode)
+            .ws_subprotocols(if self.ws_echo_subprotocol {
+                WsSubprotocols::EchoFirst
+            } else if self.ws_subprotocols.is_empty() {
+                WsSubprotocols::None
+            } else {
+                WsSubprotocols::Supported(self.ws_subprotocols.clone())
+            })
+            .ws_broadcast_to_sender(!self.ws_broadcast_skip_sender)
+            .ws_deflate(self.ws_deflate.then_some(DeflateConfig {
+                server_max_window_bits: self.ws_deflate_server_max_window_bits,
+                client_max_window_bits: self.ws_deflate_client_max_window_bits,
+                server_no_context_takeover: self.ws_deflate_server_no_context_takeover,
+                client_no_context_takeover: self.ws_deflate_client_no_context_takeover,
+            }))
+            .chaos(ChaosConfig {
+                close_probability: self.fault_close,
+                reset_probability: self.fault_reset,
+                stall_probability: self.fault_stall,
+                truncate_probability: self.fault_truncate,
+                malformed_chunk_probability: self.fault_malformed_chunk,
+                fault_after_bytes: self.fault_after_bytes,
+            })
+            .shaping(ShapingConfig {
+                bytes_per_second: self.rate_limit,
+                chunk_latency: Duration::from_millis(self.chunk_latency),
+                jitter: Duration::from_millis(self.latency_jitter),
+                websocket: self.shape_ws,
+            })
+            .max_response_delay(Duration::from_millis(self.max_response_delay))
+            .sse(SseConfig {
+                interval: Duration::from_millis(self.sse_interval),
+                count: self.sse_count,
+            })
+            .metrics(self.metrics)
+            .admin_prefix((!self.disable_admin).then(|| self.admin_prefix.clone()))
+            .shutdown_drain_period(Duration::from_millis(self.shutdown_drain_period))
+            .connection_limits(ConnectionLimits {
+                max_connections: self.max_connections,
+                mode: if self.reject_over_limit {
+                    ConnectionLimitMode::Reject
+                } else {
+                    ConnectionLimitMode::Backpressure
+                },
+                max_connections_per_ip: self.max_connections_per_ip,
+                max_concurrent_streams: self.max_concurrent_streams,
+            })
+            .request_limits(RequestLimits {
+                max_body_size: self.max_body_size,
+                header_read_timeout: self.header_read_timeout.map(Duration::from_millis),
+                request_timeout: self.request_timeout.map(Duration::from_millis),
+                idle_timeout: self.idle_timeout.map(Duration::from_millis),
+            });
+        for ip in &self.bind {
+            builder = builder.bind_addr(*ip);
+        }
+        #[cfg(feature = "tls")]
+        {
+            builder = builder.tls(self.tls_config()?);
+        }
+        #[cfg(feature = "http3")]
+        {
+            builder = builder.http3(self.http3);
+        }
+        #[cfg(unix)]
+        {
+            builder = builder
+                .unix_socket(self.unix_socket.clone())
+                .unix_socket_mode(self.unix_socket_mode)
+                .socket_activation(self.socket_activation);
+        }
+        Ok(builder)
+    }
+
+    #[cfg(feature = "tls")]
+    fn tls_config(&self) -> Result<Option<hyper_echo::TlsConfig>, std::io::Error> {
+        use hyper_echo::{ClientCertMode, TlsConfig};
+
+        let tls_config = if self.tls_self_signed {
+            let tls_config = TlsConfig::self_signed(&["localhost", "127.0.0.1", "::1"])?;
+            info!(
+                "Using self-signed certificate:\n{}",
+                tls_config.certificate_pem()
+            );
+            tls_config
+        } else if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            TlsConfig::from_pem_files(cert, key)?
+        } else if self.tls_client_ca.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--tls-client-ca requires --tls-cert or --tls-self-signed",
+            ));
+        } else {
+            return Ok(None);
+        };
+
+        match &self.tls_client_ca {
+            Some(ca_path) => {
+                let mode = if self.tls_client_cert_required {
+                    ClientCertMode::Required
+                } else {
+                    ClientCertMode::Optional
+                };
+                tls_config.with_client_auth_file(ca_path, mode).map(Some)
+            }
+            None => Ok(Some(tls_config)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn parse_octal(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value.trim_start_matches("0o"), 8)
+        .map_err(|_| format!("{value} is not an octal number"))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -69,6 +434,9 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .unwrap()
         .block_on(async move {
             let cancellation_token = CancellationToken::new();
+            // The server keeps serving for the drain period, so it gets extra time to stop after it
+            let force_exit_timeout =
+                Duration::from_millis(args.shutdown_drain_period) + Duration::from_secs(5);
             tokio::spawn({
                 let cancellation_token = cancellation_token.clone();
                 async move {
@@ -80,18 +448,27 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
                     select! {
                         _ = ctrl_c() => {},
-                        _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => {},
+                        _ = tokio::time::sleep(force_exit_timeout) => {},
                     };
                     exit(1);
                 }
             });
 
-            let mut echo_server =
-                EchoServer::new(args.port, args.http_log_level.into(), args.log_ws).await?;
-            let ws_ping_interval = args.ws_ping_interval.map(std::time::Duration::from_millis);
-            echo_server.set_ws_ping_interval(ws_ping_interval);
+            let echo_server = args.to_builder()?.build().await?;
 
-            info!("Starting echo server on {}", echo_server.local_addr());
+            for addr in echo_server.local_addrs() {
+                info!("Starting echo server on {addr}");
+            }
+            #[cfg(unix)]
+            for path in echo_server.unix_socket_paths() {
+                info!("Starting echo server on {}", path.display());
+            }
+            for addr in echo_server.raw_tcp_addrs() {
+                info!("Starting raw TCP echo on {addr}");
+            }
+            for addr in echo_server.raw_udp_addrs() {
+                info!("Starting raw UDP echo on {addr}");
+            }
             echo_server.run(cancellation_token).await
         })
         .map_err(Into::into)
@@ -0,0 +1,205 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, ready},
+};
+
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Response body data marking the chunk which [MalformedChunkIo] replaces on the wire
+pub(crate) const MALFORMED_CHUNK_MARKER: &[u8] = b"\0hyper_echo malformed chunk marker\0";
+
+/// Written instead of the marker chunk: the chunk size is not a hex number
+const MALFORMED_CHUNK: &[u8] = b"zz\r\nmalformed chunk\r\n";
+
+/// Per connection switch telling [MalformedChunkIo] to look for [MALFORMED_CHUNK_MARKER] in written data
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MalformedChunkTrigger(Arc<AtomicBool>);
+
+impl MalformedChunkTrigger {
+    pub fn arm(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn is_armed(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Passthrough,
+    /// Writing [MALFORMED_CHUNK], `written` bytes of it are already sent
+    Replacing {
+        written: usize,
+    },
+    /// The malformed chunk is sent, everything written afterwards is discarded
+    Broken,
+}
+
+/// IO wrapper replacing the HTTP/1.1 chunk carrying [MALFORMED_CHUNK_MARKER] with a malformed one.
+///
+/// HTTP framing is done by hyper, so a malformed chunk can't be produced by a response body.
+/// Instead the body sends the marker, hyper frames it as a chunk and this wrapper rewrites the chunk.
+#[pin_project]
+pub(crate) struct MalformedChunkIo<S> {
+    #[pin]
+    inner: S,
+    trigger: MalformedChunkTrigger,
+    state: State,
+}
+
+impl<S> MalformedChunkIo<S> {
+    pub fn new(inner: S, trigger: MalformedChunkTrigger) -> Self {
+        Self {
+            inner,
+            trigger,
+            state: State::Passthrough,
+        }
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for MalformedChunkIo<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for MalformedChunkIo<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut this = self.project();
+        if !this.trigger.is_armed() {
+            return this.inner.poll_write(cx, buf);
+        }
+        loop {
+            match this.state {
+                State::Broken => return Poll::Ready(Ok(buf.len())),
+                State::Passthrough => {
+                    let Some((start, _)) = find_marker_chunk(buf) else {
+                        return this.inner.poll_write(cx, buf);
+                    };
+                    if start > 0 {
+                        return this.inner.poll_write(cx, &buf[..start]);
+                    }
+                    // The marker chunk is at the start of the buffer, it is reported as written once replaced
+                    *this.state = State::Replacing { written: 0 };
+                }
+                State::Replacing { written } => {
+                    while *written < MALFORMED_CHUNK.len() {
+                        match ready!(
+                            this.inner
+                                .as_mut()
+                                .poll_write(cx, &MALFORMED_CHUNK[*written..])
+                        )? {
+                            0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                            n => *written += n,
+                        }
+                    }
+                    *this.state = State::Broken;
+                    let consumed = find_marker_chunk(buf).map_or(buf.len(), |(_, end)| end);
+                    return Poll::Ready(Ok(consumed));
+                }
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        if !self.trigger.is_armed() {
+            return self.project().inner.poll_write_vectored(cx, bufs);
+        }
+        // The marker chunk could be split between the slices
+        let buf: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        self.poll_write(cx, &buf)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Find the chunk carrying the marker: its size line if present, the marker and the trailing CRLF if present.
+///
+/// Returns the range of the chunk in `buf`.
+fn find_marker_chunk(buf: &[u8]) -> Option<(usize, usize)> {
+    let marker_start = buf
+        .windows(MALFORMED_CHUNK_MARKER.len())
+        .position(|window| window == MALFORMED_CHUNK_MARKER)?;
+    let size_line = format!("{:X}\r\n", MALFORMED_CHUNK_MARKER.len());
+    let start = if buf[..marker_start].ends_with(size_line.as_bytes()) {
+        marker_start - size_line.len()
+    } else {
+        marker_start
+    };
+    let marker_end = marker_start + MALFORMED_CHUNK_MARKER.len();
+    let end = if buf[marker_end..].starts_with(b"\r\n") {
+        marker_end + 2
+    } else {
+        marker_end
+    };
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::{MALFORMED_CHUNK_MARKER, MalformedChunkIo, MalformedChunkTrigger};
+
+    fn chunked(data: &[u8]) -> Vec<u8> {
+        [format!("{:X}\r\n", data.len()).as_bytes(), data, b"\r\n"].concat()
+    }
+
+    #[tokio::test]
+    async fn marker_chunk_is_replaced() {
+        let trigger = MalformedChunkTrigger::default();
+        let mut io = MalformedChunkIo::new(Vec::new(), trigger.clone());
+
+        io.write_all(&chunked(b"some")).await.unwrap();
+        trigger.arm();
+        let data = [
+            chunked(b"body"),
+            chunked(MALFORMED_CHUNK_MARKER),
+            chunked(b"rest"),
+        ]
+        .concat();
+        io.write_all(&data).await.unwrap();
+
+        assert_eq!(
+            io.inner,
+            b"4\r\nsome\r\n4\r\nbody\r\nzz\r\nmalformed chunk\r\n".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn data_is_untouched_when_not_armed() {
+        let mut io = MalformedChunkIo::new(Vec::new(), MalformedChunkTrigger::default());
+        let data = chunked(MALFORMED_CHUNK_MARKER);
+        io.write_all(&data).await.unwrap();
+        assert_eq!(io.inner, data);
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use fastwebsockets::OpCode;
+use hyper::{Method, StatusCode, Version};
+
+/// Path the metrics are served on when enabled
+pub const METRICS_PATH: &str = "/metrics";
+
+const METRIC_PREFIX: &str = "hyper_echo";
+
+/// Upper bounds of latency histogram buckets in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// Server metrics exposed in Prometheus text format
+#[derive(Debug)]
+pub struct Metrics {
+    started_at: Instant,
+    ready: AtomicBool,
+    http_requests: Mutex<BTreeMap<(&'static str, u16, &'static str), u64>>,
+    http_request_bytes: AtomicU64,
+    http_response_bytes: AtomicU64,
+    http_latency: Histogram,
+    active_connections: AtomicI64,
+    rejected_connections: AtomicU64,
+    active_ws_sessions: AtomicI64,
+    ws_frames: Mutex<BTreeMap<&'static str, u64>>,
+    ws_ping_timeouts: AtomicU64,
+    ws_upgrade_failures: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            ready: AtomicBool::new(true),
+            http_requests: Mutex::default(),
+            http_request_bytes: AtomicU64::default(),
+            http_response_bytes: AtomicU64::default(),
+            http_latency: Histogram::default(),
+            active_connections: AtomicI64::default(),
+            rejected_connections: AtomicU64::default(),
+            active_ws_sessions: AtomicI64::default(),
+            ws_frames: Mutex::default(),
+            ws_ping_timeouts: AtomicU64::default(),
+            ws_upgrade_failures: AtomicU64::default(),
+        }
+    }
+
+    /// Whether the server accepts new work (i.e. it is not shutting down)
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+
+    /// Time passed since the server was created
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn record_http_request(
+        &self,
+        method: &Method,
+        status: StatusCode,
+        version: Version,
+        latency: Duration,
+    ) {
+        let key = (method_name(method), status.as_u16(), version_name(version));
+        *self.http_requests.lock().unwrap().entry(key).or_default() += 1;
+        self.http_latency.observe(latency);
+    }
+
+    pub fn add_request_bytes(&self, bytes: usize) {
+        self.http_request_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_response_bytes(&self, bytes: usize) {
+        self.http_response_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a connection as active until the returned guard is dropped
+    pub fn connection_started(&self) -> ActiveGuard<'_> {
+        ActiveGuard::new(&self.active_connections)
+    }
+
+    pub fn record_rejected_connection(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a WebSocket session as active until the returned guard is dropped
+    pub fn ws_session_started(&self) -> ActiveGuard<'_> {
+        ActiveGuard::new(&self.active_ws_sessions)
+    }
+
+    pub fn record_ws_frame(&self, opcode: OpCode) {
+        *self
+            .ws_frames
+            .lock()
+            .unwrap()
+            .entry(opcode_name(opcode))
+            .or_default() += 1;
+    }
+
+    pub fn record_ws_ping_timeout(&self) {
+        self.ws_ping_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_ws_upgrade_failure(&self) {
+        self.ws_upgrade_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render all the metrics in Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "uptime_seconds",
+            "gauge",
+            "Time passed since the server was created",
+        );
+        let _ = writeln!(
+            out,
+            "{METRIC_PREFIX}_uptime_seconds {}",
+            self.uptime().as_secs_f64()
+        );
+
+        header(
+            &mut out,
+            "http_requests_total",
+            "counter",
+            "Number of processed HTTP requests",
+        );
+        for ((method, status, version), count) in self.http_requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{METRIC_PREFIX}_http_requests_total{{method=\"{method}\",status=\"{status}\",version=\"{version}\"}} {count}"
+            );
+        }
+
+        counter(
+            &mut out,
+            "http_request_bytes_total",
+            "Number of received HTTP request body bytes",
+            self.http_request_bytes.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "http_response_bytes_total",
+            "Number of sent HTTP response body bytes",
+            self.http_response_bytes.load(Ordering::Relaxed),
+        );
+
+        header(
+            &mut out,
+            "http_request_duration_seconds",
+            "histogram",
+            "Time to process an HTTP request until response headers are ready",
+        );
+        self.http_latency
+            .render(&mut out, "http_request_duration_seconds");
+
+        gauge(
+            &mut out,
+            "ready",
+            "Whether the server is ready (1) or shutting down (0)",
+            self.is_ready().into(),
+        );
+        gauge(
+            &mut out,
+            "active_connections",
+            "Number of open client connections",
+            self.active_connections.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "rejected_connections_total",
+            "Number of connections closed because of connection limits",
+            self.rejected_connections.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "active_ws_sessions",
+            "Number of open WebSocket sessions",
+            self.active_ws_sessions.load(Ordering::Relaxed),
+        );
+
+        header(
+            &mut out,
+            "ws_frames_total",
+            "counter",
+            "Number of received WebSocket frames",
+        );
+        for (opcode, count) in self.ws_frames.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{METRIC_PREFIX}_ws_frames_total{{opcode=\"{opcode}\"}} {count}"
+            );
+        }
+
+        counter(
+            &mut out,
+            "ws_ping_timeouts_total",
+            "Number of WebSocket sessions closed because of a missing pong",
+            self.ws_ping_timeouts.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "ws_upgrade_failures_total",
+            "Number of failed WebSocket upgrades",
+            self.ws_upgrade_failures.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
+
+/// Decrements an active gauge on drop
+pub struct ActiveGuard<'a>(&'a AtomicI64);
+
+impl<'a> ActiveGuard<'a> {
+    fn new(gauge: &'a AtomicI64) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{METRIC_PREFIX}_{name}_bucket{{le=\"{bound}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{METRIC_PREFIX}_{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{METRIC_PREFIX}_{name}_sum {sum}");
+        let _ = writeln!(out, "{METRIC_PREFIX}_{name}_count {count}");
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {METRIC_PREFIX}_{name} {help}");
+    let _ = writeln!(out, "# TYPE {METRIC_PREFIX}_{name} {kind}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{METRIC_PREFIX}_{name} {value}");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{METRIC_PREFIX}_{name} {value}");
+}
+
+/// Non-standard methods share one label, so clients can't create unlimited time series
+fn method_name(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "other",
+    }
+}
+
+fn version_name(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_11 => "HTTP/1.1",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "unknown",
+    }
+}
+
+fn opcode_name(opcode: OpCode) -> &'static str {
+    match opcode {
+        OpCode::Continuation => "continuation",
+        OpCode::Text => "text",
+        OpCode::Binary => "binary",
+        OpCode::Close => "close",
+        OpCode::Ping => "ping",
+        OpCode::Pong => "pong",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fastwebsockets::OpCode;
+    use hyper::{Method, StatusCode, Version};
+
+    use super::Metrics;
+
+    #[test]
+    fn metrics_are_rendered() {
+        let metrics = Metrics::new();
+        metrics.record_http_request(
+            &Method::GET,
+            StatusCode::OK,
+            Version::HTTP_11,
+            Duration::from_millis(3),
+        );
+        metrics.record_http_request(
+            &Method::from_bytes(b"RANDOM123").unwrap(),
+            StatusCode::OK,
+            Version::HTTP_11,
+            Duration::from_secs(1),
+        );
+        metrics.record_ws_frame(OpCode::Text);
+        let _connection = metrics.connection_started();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains(
+            "hyper_echo_http_requests_total{method=\"GET\",status=\"200\",version=\"HTTP/1.1\"} 1"
+        ));
+        assert!(rendered.contains(
+            "hyper_echo_http_requests_total{method=\"other\",status=\"200\",version=\"HTTP/1.1\"} 1"
+        ));
+        assert!(!rendered.contains("RANDOM123"));
+        assert!(
+            rendered.contains("hyper_echo_http_request_duration_seconds_bucket{le=\"0.0025\"} 0")
+        );
+        assert!(
+            rendered.contains("hyper_echo_http_request_duration_seconds_bucket{le=\"0.005\"} 1")
+        );
+        assert!(rendered.contains("hyper_echo_ws_frames_total{opcode=\"text\"} 1"));
+        assert!(rendered.contains("hyper_echo_active_connections 1"));
+    }
+}
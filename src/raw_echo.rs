@@ -0,0 +1,153 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, field, info, info_span, warn};
+
+use crate::connection_info::ConnectionInfo;
+
+const TCP_PREFIX: &str = "TCP:";
+const UDP_PREFIX: &str = "UDP:";
+const TCP_BUFFER_SIZE: usize = 16 * 1024;
+/// Max payload of a UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// Level of logging raw TCP and UDP echo traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawLogLevel {
+    /// No logging
+    None,
+    /// Log connections and sizes of received data
+    Summary,
+    /// Log connections and received data: text as is, binary as base64
+    Data,
+}
+
+impl TryFrom<u8> for RawLogLevel {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RawLogLevel::None),
+            1 => Ok(RawLogLevel::Summary),
+            2 => Ok(RawLogLevel::Data),
+            _ => Err(format!("Invalid raw log level {value}, expected 0, 1 or 2")),
+        }
+    }
+}
+
+/// Echo everything received on a raw TCP connection until the client closes it
+/// or the server is stopped
+pub(crate) async fn echo_tcp(
+    mut stream: TcpStream,
+    log_level: RawLogLevel,
+    connection_info: ConnectionInfo,
+    cancellation_token: CancellationToken,
+) {
+    let span = info_span!(
+        "tcp client",
+        ip = connection_info.client_addr.ip().map(field::debug),
+        id = connection_info.id
+    );
+    async move {
+        log_event(log_level, TCP_PREFIX, "connection established");
+        let mut buffer = vec![0; TCP_BUFFER_SIZE];
+        loop {
+            let Some(read) = cancellation_token
+                .run_until_cancelled(stream.read(&mut buffer))
+                .await
+            else {
+                break;
+            };
+            let len = match read {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) => {
+                    warn!("{TCP_PREFIX} error reading connection: {e}");
+                    return;
+                }
+            };
+            log_data(log_level, TCP_PREFIX, &buffer[..len]);
+            match cancellation_token
+                .run_until_cancelled(stream.write_all(&buffer[..len]))
+                .await
+            {
+                Some(Ok(())) => {}
+                Some(Err(e)) => {
+                    warn!("{TCP_PREFIX} error writing connection: {e}");
+                    return;
+                }
+                None => break,
+            }
+        }
+        // Let the client read the echoed data before the connection is closed
+        let _ = stream.shutdown().await;
+        log_event(log_level, TCP_PREFIX, "connection closed");
+    }
+    .instrument(span)
+    .await
+}
+
+/// Echo every datagram received on the socket back to its sender until cancelled.
+/// Each datagram gets its own id from `connection_ids`.
+pub(crate) async fn echo_udp(
+    socket: Arc<UdpSocket>,
+    log_level: RawLogLevel,
+    connection_ids: Arc<AtomicU64>,
+    cancellation_token: CancellationToken,
+) {
+    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+    while let Some(received) = cancellation_token
+        .run_until_cancelled(socket.recv_from(&mut buffer))
+        .await
+    {
+        let (len, client_addr) = match received {
+            Ok(received) => received,
+            // E.g. ICMP port unreachable for a previous reply, the socket is still usable
+            Err(e) => {
+                warn!("{UDP_PREFIX} error receiving datagram: {e}");
+                continue;
+            }
+        };
+        let span = datagram_span(client_addr, connection_ids.fetch_add(1, Ordering::Relaxed));
+        span.in_scope(|| log_data(log_level, UDP_PREFIX, &buffer[..len]));
+        if let Err(e) = socket.send_to(&buffer[..len], client_addr).await {
+            span.in_scope(|| warn!("{UDP_PREFIX} error sending datagram: {e}"));
+        }
+    }
+}
+
+fn datagram_span(client_addr: SocketAddr, id: u64) -> Span {
+    info_span!("udp client", ip = ?client_addr.ip(), id)
+}
+
+fn log_event(log_level: RawLogLevel, prefix: &str, event: &str) {
+    if log_level != RawLogLevel::None {
+        info!("{prefix} {event}");
+    }
+}
+
+fn log_data(log_level: RawLogLevel, prefix: &str, data: &[u8]) {
+    match log_level {
+        RawLogLevel::None => {}
+        RawLogLevel::Summary => info!("{prefix} received {} bytes", data.len()),
+        RawLogLevel::Data => match std::str::from_utf8(data) {
+            Ok(text) => info!("{prefix} received {} bytes: {text}", data.len()),
+            Err(_) => info!(
+                "{prefix} received {} bytes (base64): {}",
+                data.len(),
+                STANDARD.encode(data)
+            ),
+        },
+    }
+}
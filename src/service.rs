@@ -1,15 +1,33 @@
-use crate::{log_utils::HttpLogLevel, ws_logger::WsLogger};
-use fastwebsockets::upgrade::is_upgrade_request;
+use crate::{
+    config::Config, connection_info::ConnectionInfo, metrics::Metrics, ws_logger::WsLogger,
+};
+use chaos::ChaosLayer;
+use control::ResponseControl;
+use metrics::MetricsLayer;
+use request_limits::RequestLimitsLayer;
+
+pub use chaos::ChaosConfig;
 use http_body_util::combinators::BoxBody;
 use hyper::{
     Request, Response,
     body::{Body, Bytes},
 };
-use std::time::Duration;
-use std::{convert::Infallible, future::Future, net::IpAddr, pin::Pin};
+pub use shaping::ShapingConfig;
+pub use sse::SseConfig;
+use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc};
 use tokio_util::sync::CancellationToken;
+pub(crate) use ws::WsHub;
+pub use ws::{DeflateConfig, WsFragmentMode, WsSubprotocols};
 
+mod admin;
+mod chaos;
+mod control;
 mod http;
+mod inspect;
+mod metrics;
+mod request_limits;
+mod shaping;
+mod sse;
 mod ws;
 
 macro_rules! BoxedError {
@@ -20,93 +38,122 @@ macro_rules! BoxedError {
 
 pub(in crate::service) use BoxedError;
 
+type EchoResponse = Response<BoxBody<Bytes, BoxedError!()>>;
+
+#[cfg(feature = "tower_trace")]
+type TraceResponseBody = tower_http::trace::ResponseBody<
+    BoxBody<Bytes, BoxedError!()>,
+    tower_http::classify::NeverClassifyEos<tower_http::classify::ServerErrorsFailureClass>,
+    crate::http_loggers::BodyLogger,
+>;
+
 #[cfg(feature = "custom_trace")]
 pub fn make_service<B>(
-    log_level: HttpLogLevel,
-    ws_logging_enabled: bool,
-    ws_ping_interval: Option<Duration>,
-    client_ip: IpAddr,
-    id: u64,
+    config: Arc<Config>,
+    connection_info: ConnectionInfo,
+    metrics: Arc<Metrics>,
+    ws_hub: Arc<WsHub>,
     cancellation_token: CancellationToken,
 ) -> impl tower::Service<
     Request<B>,
-    Response = Response<BoxBody<Bytes, BoxedError!()>>,
-    Error = Infallible,
+    Response = EchoResponse,
+    Error = BoxedError!(),
     Future = impl Future,
 > + Clone
 where
-    B: Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
 {
     use crate::custom_logger::LoggerLayer;
 
+    let log_level = config.http_log_level;
+    let chaos_layer = ChaosLayer::new(
+        config.clone(),
+        connection_info.malformed_chunk.clone(),
+        cancellation_token.clone(),
+    );
+    let metrics_layer = MetricsLayer::new(metrics.clone());
+    let limits_layer = RequestLimitsLayer::new(config.request_limits.clone());
     let svc = EchoService::new(
-        ws_logging_enabled,
-        ws_ping_interval,
-        client_ip,
-        id,
+        config,
+        connection_info.clone(),
+        metrics,
+        ws_hub,
         cancellation_token,
     );
     tower::ServiceBuilder::new()
-        .layer(LoggerLayer::new(log_level, client_ip, id))
+        .layer(LoggerLayer::new(log_level, connection_info))
+        .layer(metrics_layer)
+        .layer(limits_layer)
+        .layer(chaos_layer)
         .service(svc)
 }
 
 #[cfg(feature = "tower_trace")]
 pub fn make_service<B>(
-    http_log_level: HttpLogLevel,
-    ws_logging_enabled: bool,
-    ws_ping_interval: Option<Duration>,
-    client_ip: IpAddr,
-    id: u64,
+    config: Arc<Config>,
+    connection_info: ConnectionInfo,
+    metrics: Arc<Metrics>,
+    ws_hub: Arc<WsHub>,
     cancellation_token: CancellationToken,
 ) -> impl tower::Service<
     Request<B>,
-    Response = Response<
-        tower_http::trace::ResponseBody<
-            BoxBody<Bytes, BoxedError!()>,
-            tower_http::classify::NeverClassifyEos<tower_http::classify::ServerErrorsFailureClass>,
-            crate::http_loggers::BodyLogger,
-        >,
-    >,
+    Response = Response<TraceResponseBody>,
     Future = impl Future,
-    Error = Infallible,
+    Error = BoxedError!(),
 > + Clone
 where
-    B: Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
 {
     use crate::http_loggers::{BodyLogger, OnRequestLogger, OnResponseLogger, SpanMaker};
     use tower_http::trace::TraceLayer;
 
+    let http_log_level = config.http_log_level;
+    let chaos_layer = ChaosLayer::new(
+        config.clone(),
+        connection_info.malformed_chunk.clone(),
+        cancellation_token.clone(),
+    );
+    let metrics_layer = MetricsLayer::new(metrics.clone());
+    let limits_layer = RequestLimitsLayer::new(config.request_limits.clone());
     let echo_service = EchoService::new(
-        ws_logging_enabled,
-        ws_ping_interval,
-        client_ip,
-        id,
+        config,
+        connection_info.clone(),
+        metrics,
+        ws_hub,
         cancellation_token,
     );
 
-    let svc = tower::ServiceBuilder::new()
+    tower::ServiceBuilder::new()
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(SpanMaker::new(client_ip, id))
+                .make_span_with(SpanMaker::new(connection_info))
                 .on_request(OnRequestLogger::new(http_log_level))
                 .on_response(OnResponseLogger::new(http_log_level))
                 .on_body_chunk(BodyLogger::new(http_log_level)),
         )
-        .service(echo_service);
-    svc
+        .layer(metrics_layer)
+        .layer(limits_layer)
+        .layer(chaos_layer)
+        .service(echo_service)
 }
 
 #[derive(Debug, Clone)]
 struct EchoService {
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
     ws_session_data: ws::SessionData,
+    connection_info: ConnectionInfo,
+    cancellation_token: CancellationToken,
 }
 
 impl<B> tower::Service<Request<B>> for EchoService
 where
-    B: Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
 {
-    type Response = Response<BoxBody<Bytes, BoxedError!()>>;
+    type Response = EchoResponse;
 
     type Error = Infallible;
 
@@ -120,36 +167,107 @@ where
     }
 
     fn call(&mut self, req: Request<B>) -> Self::Future {
+        let config = self.config.clone();
+        let metrics = self.metrics.clone();
         let ws_session_data = self.ws_session_data.clone();
-        Box::pin(async move { process_request(req, ws_session_data) })
+        let connection_info = self.connection_info.clone();
+        let cancellation_token = self.cancellation_token.clone();
+        Box::pin(async move {
+            #[cfg_attr(not(feature = "http3"), allow(unused_mut))]
+            let mut response = process_request(
+                req,
+                &config,
+                &metrics,
+                ws_session_data,
+                &connection_info,
+                cancellation_token,
+            )
+            .await?;
+            #[cfg(feature = "http3")]
+            if let Some(port) = connection_info.h3_port {
+                crate::http3::add_alt_svc(response.headers_mut(), port);
+            }
+            Ok(response)
+        })
     }
 }
 
 impl EchoService {
     pub fn new(
-        ws_logging_enabled: bool,
-        ws_ping_interval: Option<Duration>,
-        client_ip: IpAddr,
-        id: u64,
+        config: Arc<Config>,
+        connection_info: ConnectionInfo,
+        metrics: Arc<Metrics>,
+        ws_hub: Arc<WsHub>,
         cancellation_token: CancellationToken,
     ) -> Self {
-        let ws_logger = WsLogger::new(ws_logging_enabled, client_ip, id);
-        let ws_session_data = ws::SessionData::new(ws_logger, ws_ping_interval, cancellation_token);
+        let ws_logger = WsLogger::new(config.ws_logging_enabled, &connection_info);
+        let ws_session_data = ws::SessionData::new(
+            ws_logger,
+            config.clone(),
+            metrics.clone(),
+            ws_hub,
+            cancellation_token.clone(),
+        );
 
-        Self { ws_session_data }
+        Self {
+            config,
+            metrics,
+            ws_session_data,
+            connection_info,
+            cancellation_token,
+        }
     }
 }
 
-fn process_request<B>(
-    request: Request<B>,
+/// Requests answered by the server itself instead of being echoed: WebSocket upgrades, admin endpoints and metrics
+fn is_service_request<B>(request: &Request<B>, config: &Config) -> bool {
+    ws::is_upgrade_request(request)
+        || admin::admin_path(request, config).is_some()
+        || (config.metrics_enabled && metrics::is_metrics_request(request))
+}
+
+async fn process_request<B>(
+    mut request: Request<B>,
+    config: &Config,
+    metrics: &Metrics,
     ws_session_data: ws::SessionData,
-) -> Result<Response<BoxBody<Bytes, BoxedError!()>>, Infallible>
+    connection_info: &ConnectionInfo,
+    cancellation_token: CancellationToken,
+) -> Result<EchoResponse, Infallible>
 where
-    B: Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
 {
-    if is_upgrade_request(&request) {
-        ws::run_session(request, ws_session_data)
+    if ws::is_upgrade_request(&request) {
+        return ws::run_session(request, ws_session_data);
+    }
+    if let Some(path) = admin::admin_path(&request, config) {
+        return Ok(admin::respond(&request, path, config, metrics));
+    }
+    if config.metrics_enabled && metrics::is_metrics_request(&request) {
+        return Ok(metrics::metrics_response(metrics));
+    }
+
+    let response_control = match ResponseControl::from_request(
+        &mut request,
+        &config.shaping,
+        config.max_response_delay,
+    ) {
+        Ok(response_control) => response_control,
+        Err(e) => return Ok(control::bad_request(e)),
+    };
+
+    #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+    let mut response = if inspect::is_inspect_request(&request) {
+        inspect::inspect(request, connection_info).await?
+    } else if sse::is_sse_request(&request) {
+        sse::stream(request, &config.sse, cancellation_token.clone()).await?
     } else {
-        http::echo(request)
+        http::echo(request)?
+    };
+    #[cfg(feature = "tls")]
+    if let Some(client_cert) = &connection_info.client_cert {
+        client_cert.add_headers(response.headers_mut());
     }
+    Ok(response_control.apply(response, &cancellation_token).await)
 }
@@ -0,0 +1,132 @@
+use http_body_util::Full;
+use hyper::{Request, Response, StatusCode, body::Bytes, header::CONTENT_TYPE};
+use serde_json::{Value, json};
+
+use super::{EchoResponse, WsSubprotocols, http::to_boxed_body, inspect::json_response};
+use crate::{config::Config, metrics::Metrics};
+
+const HEALTH_PATH: &str = "/healthz";
+const READY_PATH: &str = "/readyz";
+const INFO_PATH: &str = "/info";
+
+/// Returns the path of an admin endpoint relative to the admin prefix
+/// or `None` if the request is not an admin request
+pub(in crate::service) fn admin_path<'a, B>(
+    request: &'a Request<B>,
+    config: &Config,
+) -> Option<&'a str> {
+    let prefix = config.admin_prefix.as_deref()?;
+    request
+        .uri()
+        .path()
+        .strip_prefix(prefix)
+        .filter(|rest| rest.starts_with('/'))
+}
+
+/// Respond to a request to an admin endpoint
+/// - `path` - path of the endpoint relative to the admin prefix
+pub(in crate::service) fn respond<B>(
+    request: &Request<B>,
+    path: &str,
+    config: &Config,
+    metrics: &Metrics,
+) -> EchoResponse {
+    match path {
+        HEALTH_PATH => text_response(StatusCode::OK, "ok"),
+        READY_PATH if !metrics.is_ready() => {
+            text_response(StatusCode::SERVICE_UNAVAILABLE, "shutting down")
+        }
+        READY_PATH => text_response(StatusCode::OK, "ready"),
+        INFO_PATH => json_response(StatusCode::OK, request.version(), info(config, metrics)),
+        _ => text_response(StatusCode::NOT_FOUND, "unknown admin endpoint"),
+    }
+}
+
+fn info(config: &Config, metrics: &Metrics) -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime_seconds": metrics.uptime().as_secs_f64(),
+        "features": enabled_features(),
+        "config": {
+            "http_log_level": format!("{:?}", config.http_log_level),
+            "raw_log_level": format!("{:?}", config.raw_log_level),
+            "ws_logging": config.ws_logging_enabled,
+            "ws_ping_interval_ms": config.ws_ping_interval.map(|interval| interval.as_millis() as u64),
+            "ws_max_message_size": config.ws_max_message_size,
+            "ws_max_frame_size": config.ws_max_frame_size,
+            "ws_auto_pong": config.ws_auto_pong,
+            "ws_pong_timeout_ms": config.ws_pong_timeout.map(|timeout| timeout.as_millis() as u64),
+            "ws_limit_close_code": config.ws_limit_close_code,
+            "ws_limit_close_reason": config.ws_limit_close_reason,
+            "ws_fragment_mode": format!("{:?}", config.ws_fragment_mode),
+            "ws_subprotocols": match &config.ws_subprotocols {
+                WsSubprotocols::None => Value::Null,
+                WsSubprotocols::Supported(protocols) => json!(protocols),
+                WsSubprotocols::EchoFirst => json!("echo_first"),
+            },
+            "ws_broadcast_to_sender": config.ws_broadcast_to_sender,
+            "ws_deflate": config.ws_deflate.as_ref().map(|deflate| json!({
+                "server_max_window_bits": deflate.server_max_window_bits,
+                "client_max_window_bits": deflate.client_max_window_bits,
+                "server_no_context_takeover": deflate.server_no_context_takeover,
+                "client_no_context_takeover": deflate.client_no_context_takeover,
+            })),
+            "chaos": {
+                "close_probability": config.chaos.close_probability,
+                "reset_probability": config.chaos.reset_probability,
+                "stall_probability": config.chaos.stall_probability,
+                "truncate_probability": config.chaos.truncate_probability,
+                "malformed_chunk_probability": config.chaos.malformed_chunk_probability,
+                "fault_after_bytes": config.chaos.fault_after_bytes,
+            },
+            "shaping": {
+                "bytes_per_second": config.shaping.bytes_per_second,
+                "chunk_latency_ms": config.shaping.chunk_latency.as_millis() as u64,
+                "jitter_ms": config.shaping.jitter.as_millis() as u64,
+                "websocket": config.shaping.websocket,
+            },
+            "sse": {
+                "interval_ms": config.sse.interval.as_millis() as u64,
+                "count": config.sse.count,
+            },
+            "metrics": config.metrics_enabled,
+            "admin_prefix": config.admin_prefix,
+            "max_response_delay_ms": config.max_response_delay.as_millis() as u64,
+            "shutdown_drain_period_ms": config.shutdown_drain_period.as_millis() as u64,
+            "limits": {
+                "max_connections": config.limits.max_connections,
+                "mode": format!("{:?}", config.limits.mode),
+                "max_connections_per_ip": config.limits.max_connections_per_ip,
+                "max_concurrent_streams": config.limits.max_concurrent_streams,
+            },
+            "request_limits": {
+                "max_body_size": config.request_limits.max_body_size,
+                "header_read_timeout_ms": config.request_limits.header_read_timeout.map(|timeout| timeout.as_millis() as u64),
+                "request_timeout_ms": config.request_limits.request_timeout.map(|timeout| timeout.as_millis() as u64),
+                "idle_timeout_ms": config.request_limits.idle_timeout.map(|timeout| timeout.as_millis() as u64),
+            },
+        },
+    })
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    let features = [
+        ("tower_trace", cfg!(feature = "tower_trace")),
+        ("custom_trace", cfg!(feature = "custom_trace")),
+        ("tls", cfg!(feature = "tls")),
+        ("http3", cfg!(feature = "http3")),
+    ];
+    features
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+}
+
+fn text_response(status: StatusCode, text: &'static str) -> EchoResponse {
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Full::new(Bytes::from_static(text.as_bytes())))
+        .unwrap();
+    to_boxed_body(response)
+}
@@ -0,0 +1,365 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http_body_util::combinators::BoxBody;
+use hyper::{
+    Request, Response, Version,
+    body::{Body, Bytes, Frame},
+    header::CONTENT_LENGTH,
+};
+use pin_project::pin_project;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+use tower::{Layer, Service};
+
+use super::{BoxedError, EchoResponse, control::bad_request, is_service_request};
+use crate::{
+    config::Config,
+    malformed_chunk::{MALFORMED_CHUNK_MARKER, MalformedChunkTrigger},
+};
+
+const FAULT_HEADER: &str = "x-echo-fault";
+const FAULT_AFTER_BYTES_HEADER: &str = "x-echo-fault-after-bytes";
+
+/// Fault injection settings of an [EchoServer](crate::EchoServer).
+///
+/// Each probability is a number from `0.0` (never) to `1.0` (always) and is checked for every request.
+/// WebSocket upgrades, admin endpoints and metrics are never faulted so probes and scrapes stay reliable.
+/// Regardless of the probabilities a fault could be requested deterministically with request headers:
+/// - `x-echo-fault: close|reset|stall|truncate|malformed_chunk` - the fault to inject
+/// - `x-echo-fault-after-bytes: N` - number of body bytes to send before the fault
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChaosConfig {
+    /// Probability to close the connection before sending response headers
+    pub close_probability: f64,
+    /// Probability to abort the connection in the middle of the response body
+    pub reset_probability: f64,
+    /// Probability to stop sending the response body without closing the connection
+    pub stall_probability: f64,
+    /// Probability to end the response body prematurely
+    pub truncate_probability: f64,
+    /// Probability to send a chunk with invalid size and close the connection.
+    /// Only HTTP/1.1 has chunked encoding, other versions get a reset instead.
+    pub malformed_chunk_probability: f64,
+    /// Number of response body bytes sent before a body fault happens
+    pub fault_after_bytes: usize,
+}
+
+impl ChaosConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let probabilities = [
+            ("close", self.close_probability),
+            ("reset", self.reset_probability),
+            ("stall", self.stall_probability),
+            ("truncate", self.truncate_probability),
+            ("malformed chunk", self.malformed_chunk_probability),
+        ];
+        for (name, probability) in probabilities {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(format!(
+                    "Probability of {name} fault must be in range [0, 1], got {probability}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn random_fault(&self) -> Option<Fault> {
+        let faults = [
+            (Fault::Close, self.close_probability),
+            (Fault::Body(BodyFault::Reset), self.reset_probability),
+            (Fault::Body(BodyFault::Stall), self.stall_probability),
+            (Fault::Body(BodyFault::Truncate), self.truncate_probability),
+            (
+                Fault::Body(BodyFault::MalformedChunk),
+                self.malformed_chunk_probability,
+            ),
+        ];
+        faults
+            .into_iter()
+            .find(|(_, probability)| *probability > 0.0 && rand::random::<f64>() < *probability)
+            .map(|(fault, _)| fault)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    /// Close the connection before response headers
+    Close,
+    /// Fault of the response body after the headers are sent
+    Body(BodyFault),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFault {
+    Reset,
+    Stall,
+    Truncate,
+    MalformedChunk,
+}
+
+impl Fault {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "close" => Some(Self::Close),
+            "reset" => Some(Self::Body(BodyFault::Reset)),
+            "stall" => Some(Self::Body(BodyFault::Stall)),
+            "truncate" => Some(Self::Body(BodyFault::Truncate)),
+            "malformed_chunk" => Some(Self::Body(BodyFault::MalformedChunk)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(in crate::service) struct ChaosLayer {
+    config: Arc<Config>,
+    malformed_chunk: MalformedChunkTrigger,
+    cancellation_token: CancellationToken,
+}
+
+impl ChaosLayer {
+    pub fn new(
+        config: Arc<Config>,
+        malformed_chunk: MalformedChunkTrigger,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        Self {
+            config,
+            malformed_chunk,
+            cancellation_token,
+        }
+    }
+}
+
+impl<S> Layer<S> for ChaosLayer {
+    type Service = ChaosService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ChaosService {
+            inner,
+            config: self.config.clone(),
+            malformed_chunk: self.malformed_chunk.clone(),
+            cancellation_token: self.cancellation_token.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(in crate::service) struct ChaosService<S> {
+    inner: S,
+    config: Arc<Config>,
+    malformed_chunk: MalformedChunkTrigger,
+    cancellation_token: CancellationToken,
+}
+
+impl<S> ChaosService<S> {
+    /// Fault requested by the request headers takes precedence over a random one.
+    /// Returns an error for invalid fault headers.
+    fn choose_fault<B>(&self, request: &Request<B>) -> Result<(Option<Fault>, usize), String> {
+        if is_service_request(request, &self.config) {
+            return Ok((None, 0));
+        }
+        let chaos = &self.config.chaos;
+        let headers = request.headers();
+        let after_bytes = match headers.get(FAULT_AFTER_BYTES_HEADER) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid number of bytes: {}",
+                        String::from_utf8_lossy(value.as_bytes())
+                    )
+                })?,
+            None => chaos.fault_after_bytes,
+        };
+        let fault = match headers.get(FAULT_HEADER) {
+            Some(value) => Some(value.to_str().ok().and_then(Fault::from_name).ok_or_else(
+                || {
+                    format!(
+                        "Invalid fault: {}",
+                        String::from_utf8_lossy(value.as_bytes())
+                    )
+                },
+            )?),
+            None => chaos.random_fault(),
+        };
+        let fault = fault.map(|fault| match fault {
+            Fault::Body(BodyFault::MalformedChunk) if request.version() != Version::HTTP_11 => {
+                Fault::Body(BodyFault::Reset)
+            }
+            fault => fault,
+        });
+        Ok((fault, after_bytes))
+    }
+}
+
+impl<S, B> Service<Request<B>> for ChaosService<S>
+where
+    S: Service<Request<B>, Response = EchoResponse, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = EchoResponse;
+    type Error = BoxedError!();
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let fault = match self.choose_fault(&request) {
+            Ok((None, _)) => None,
+            Ok((Some(Fault::Close), _)) => {
+                return Box::pin(async { Err("Connection closed by fault injection".into()) });
+            }
+            Ok((Some(Fault::Body(fault)), after_bytes)) => Some((fault, after_bytes)),
+            Err(e) => return Box::pin(async { Ok(bad_request(e)) }),
+        };
+
+        let cancellation_token = self.cancellation_token.clone();
+        let malformed_chunk = self.malformed_chunk.clone();
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await?;
+            let Some((fault, after_bytes)) = fault else {
+                return Ok(response);
+            };
+            let (mut parts, body) = response.into_parts();
+            // Otherwise hyper aborts the connection on the short body and truncate becomes a reset.
+            // Without a length hyper also uses chunked encoding needed by the malformed chunk.
+            if matches!(fault, BodyFault::Truncate | BodyFault::MalformedChunk) {
+                parts.headers.remove(CONTENT_LENGTH);
+            }
+            let body = FaultyBody::new(
+                body,
+                fault,
+                after_bytes,
+                malformed_chunk,
+                cancellation_token,
+            );
+            Ok(Response::from_parts(parts, BoxBody::new(body)))
+        })
+    }
+}
+
+#[pin_project]
+struct FaultyBody<B> {
+    #[pin]
+    inner: B,
+    fault: BodyFault,
+    remaining_bytes: usize,
+    yielded: bool,
+    malformed_chunk: Option<MalformedChunkTrigger>,
+    #[pin]
+    stall: WaitForCancellationFutureOwned,
+}
+
+impl<B> FaultyBody<B> {
+    fn new(
+        inner: B,
+        fault: BodyFault,
+        after_bytes: usize,
+        malformed_chunk: MalformedChunkTrigger,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        Self {
+            inner,
+            fault,
+            remaining_bytes: after_bytes,
+            yielded: false,
+            malformed_chunk: Some(malformed_chunk),
+            stall: cancellation_token.cancelled_owned(),
+        }
+    }
+}
+
+impl<B> Body for FaultyBody<B>
+where
+    B: Body<Data = Bytes, Error = BoxedError!()>,
+{
+    type Data = Bytes;
+    type Error = BoxedError!();
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if *this.remaining_bytes == 0 {
+            return match this.fault {
+                // Yield once so the already sent part of the body is flushed before the reset
+                BodyFault::Reset if !*this.yielded => {
+                    *this.yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                BodyFault::Reset => {
+                    Poll::Ready(Some(Err("Connection reset by fault injection".into())))
+                }
+                BodyFault::Truncate => Poll::Ready(None),
+                // Send the marker replaced by a malformed chunk on the wire, then reset as usual
+                BodyFault::MalformedChunk => match this.malformed_chunk.take() {
+                    Some(trigger) => {
+                        trigger.arm();
+                        Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(
+                            MALFORMED_CHUNK_MARKER,
+                        )))))
+                    }
+                    None if !*this.yielded => {
+                        *this.yielded = true;
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                    None => {
+                        Poll::Ready(Some(Err("Connection closed after malformed chunk".into())))
+                    }
+                },
+                // Stall until the server is stopped
+                BodyFault::Stall => this
+                    .stall
+                    .poll(cx)
+                    .map(|_| Some(Err("Server is shutting down".into()))),
+            };
+        }
+
+        match this.inner.poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => {
+                let frame = match frame.into_data() {
+                    Ok(mut data) => {
+                        if data.len() >= *this.remaining_bytes {
+                            data.truncate(*this.remaining_bytes);
+                        }
+                        *this.remaining_bytes -= data.len();
+                        Frame::data(data)
+                    }
+                    Err(frame) => frame,
+                };
+                Poll::Ready(Some(Ok(frame)))
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChaosConfig;
+
+    #[test]
+    fn chaos_config_validation() {
+        assert!(ChaosConfig::default().validate().is_ok());
+
+        let config = ChaosConfig {
+            reset_probability: 1.5,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::time::Duration;
+
+use http_body_util::{Full, combinators::BoxBody};
+use hyper::{
+    HeaderMap, Request, Response, StatusCode,
+    body::Bytes,
+    header::{CONTENT_LENGTH, HeaderName, HeaderValue, TRANSFER_ENCODING},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    EchoResponse,
+    http::to_boxed_body,
+    shaping::{ShapedBody, ShapingConfig},
+};
+
+/// Prefix of request headers controlling the response. Such headers are never echoed back.
+const CONTROL_HEADER_PREFIX: &str = "x-echo-";
+
+const STATUS_HEADER: &str = "x-echo-status";
+const DELAY_HEADER: &str = "x-echo-delay-ms";
+const SET_HEADER_HEADER: &str = "x-echo-set-header";
+const REMOVE_HEADER_HEADER: &str = "x-echo-remove-header";
+const BODY_HEADER: &str = "x-echo-body";
+const RATE_HEADER: &str = "x-echo-rate-bps";
+const LATENCY_HEADER: &str = "x-echo-chunk-latency-ms";
+const JITTER_HEADER: &str = "x-echo-jitter-ms";
+
+// Query parameters are prefixed so that ordinary query strings like `?status=active` are still echoed
+const STATUS_PARAM: &str = "echo_status";
+const DELAY_PARAM: &str = "echo_delay";
+const SET_HEADER_PARAM: &str = "echo_set_header";
+const REMOVE_HEADER_PARAM: &str = "echo_remove_header";
+const BODY_PARAM: &str = "echo_body";
+const RATE_PARAM: &str = "echo_rate";
+const LATENCY_PARAM: &str = "echo_latency";
+const JITTER_PARAM: &str = "echo_jitter";
+
+#[derive(Debug, Clone, Copy)]
+enum ControlKind {
+    Status,
+    Delay,
+    SetHeader,
+    RemoveHeader,
+    Body,
+    Rate,
+    Latency,
+    Jitter,
+}
+
+impl ControlKind {
+    fn from_header(name: &str) -> Option<Self> {
+        match name {
+            STATUS_HEADER => Some(Self::Status),
+            DELAY_HEADER => Some(Self::Delay),
+            SET_HEADER_HEADER => Some(Self::SetHeader),
+            REMOVE_HEADER_HEADER => Some(Self::RemoveHeader),
+            BODY_HEADER => Some(Self::Body),
+            RATE_HEADER => Some(Self::Rate),
+            LATENCY_HEADER => Some(Self::Latency),
+            JITTER_HEADER => Some(Self::Jitter),
+            _ => None,
+        }
+    }
+
+    fn from_param(name: &str) -> Option<Self> {
+        match name {
+            STATUS_PARAM => Some(Self::Status),
+            DELAY_PARAM => Some(Self::Delay),
+            SET_HEADER_PARAM => Some(Self::SetHeader),
+            REMOVE_HEADER_PARAM => Some(Self::RemoveHeader),
+            BODY_PARAM => Some(Self::Body),
+            RATE_PARAM => Some(Self::Rate),
+            LATENCY_PARAM => Some(Self::Latency),
+            JITTER_PARAM => Some(Self::Jitter),
+            _ => None,
+        }
+    }
+}
+
+/// Response modifications requested by a client.
+///
+/// Could be set by request headers:
+/// - `x-echo-status: 503` - status code of the response
+/// - `x-echo-delay-ms: 200` - delay before sending the response, at most the configured max response delay
+/// - `x-echo-set-header: name: value` - add a header to the response (could be repeated)
+/// - `x-echo-remove-header: name` - remove an echoed header from the response (could be repeated)
+/// - `x-echo-body: text` - send the provided body instead of echoing the request body
+/// - `x-echo-rate-bps`, `x-echo-chunk-latency-ms` and `x-echo-jitter-ms` - see [ShapingConfig]
+///
+/// or by query parameters `echo_status`, `echo_delay`, `echo_set_header`, `echo_remove_header`, `echo_body`,
+/// `echo_rate`, `echo_latency` and `echo_jitter` with the same meaning. Headers take precedence over query parameters.
+#[derive(Debug, Default)]
+pub(in crate::service) struct ResponseControl {
+    status: Option<StatusCode>,
+    delay: Option<Duration>,
+    set_headers: Vec<(HeaderName, HeaderValue)>,
+    remove_headers: Vec<HeaderName>,
+    body: Option<Bytes>,
+    shaping: ShapingConfig,
+}
+
+impl ResponseControl {
+    /// Parse control query parameters and headers and remove all the control headers from the request.
+    ///
+    /// - `shaping` - server wide traffic shaping settings which could be overridden by the request
+    /// - `max_delay` - max delay a request could ask for
+    ///
+    /// Returns an error message if some control value is invalid.
+    pub fn from_request<B>(
+        request: &mut Request<B>,
+        shaping: &ShapingConfig,
+        max_delay: Duration,
+    ) -> Result<Self, String> {
+        let mut control = Self {
+            shaping: shaping.clone(),
+            ..Default::default()
+        };
+
+        let query = request.uri().query().unwrap_or_default().to_string();
+        for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+            if let Some(kind) = ControlKind::from_param(&name) {
+                control.set(kind, &value)?;
+            }
+        }
+
+        for (name, value) in take_control_headers(request.headers_mut()) {
+            if let Some(kind) = ControlKind::from_header(name.as_str()) {
+                let value = value
+                    .to_str()
+                    .map_err(|_| format!("Invalid value of {name}"))?;
+                control.set(kind, value)?;
+            }
+        }
+        if let Some(delay) = control.delay
+            && delay > max_delay
+        {
+            return Err(format!(
+                "Delay {} ms exceeds the limit of {} ms",
+                delay.as_millis(),
+                max_delay.as_millis()
+            ));
+        }
+        Ok(control)
+    }
+
+    fn set(&mut self, kind: ControlKind, value: &str) -> Result<(), String> {
+        match kind {
+            ControlKind::Status => {
+                let status = value
+                    .parse::<u16>()
+                    .ok()
+                    .and_then(|code| StatusCode::from_u16(code).ok())
+                    // Informational responses can't replace the final response
+                    .filter(|status| !status.is_informational())
+                    .ok_or_else(|| format!("Invalid status code: {value}"))?;
+                self.status = Some(status);
+            }
+            ControlKind::Delay => {
+                self.delay = Some(parse_millis(value)?);
+            }
+            ControlKind::SetHeader => {
+                let (header_name, header_value) = value
+                    .split_once(':')
+                    .ok_or_else(|| format!("Invalid header, expected 'name: value': {value}"))?;
+                let header_name = HeaderName::try_from(header_name.trim())
+                    .map_err(|_| format!("Invalid header name: {header_name}"))?;
+                let header_value = HeaderValue::try_from(header_value.trim())
+                    .map_err(|_| format!("Invalid header value: {header_value}"))?;
+                self.set_headers.push((header_name, header_value));
+            }
+            ControlKind::RemoveHeader => {
+                let header_name = HeaderName::try_from(value.trim())
+                    .map_err(|_| format!("Invalid header name: {value}"))?;
+                self.remove_headers.push(header_name);
+            }
+            ControlKind::Body => {
+                self.body = Some(Bytes::from(value.to_string()));
+            }
+            ControlKind::Rate => {
+                let rate = value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|rate| *rate > 0)
+                    .ok_or_else(|| format!("Invalid rate: {value}"))?;
+                self.shaping.bytes_per_second = Some(rate);
+            }
+            ControlKind::Latency => {
+                self.shaping.chunk_latency = parse_millis(value)?;
+            }
+            ControlKind::Jitter => {
+                self.shaping.jitter = parse_millis(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the requested delay (or until the server stops) and modify the response
+    pub async fn apply(
+        self,
+        response: EchoResponse,
+        cancellation_token: &CancellationToken,
+    ) -> EchoResponse {
+        if let Some(delay) = self.delay {
+            cancellation_token
+                .run_until_cancelled(tokio::time::sleep(delay))
+                .await;
+        }
+
+        let mut response = match self.body {
+            Some(body) => {
+                let (mut parts, _) = response.into_parts();
+                parts.headers.remove(CONTENT_LENGTH);
+                parts.headers.remove(TRANSFER_ENCODING);
+                to_boxed_body(Response::from_parts(parts, Full::new(body)))
+            }
+            None => response,
+        };
+
+        if let Some(status) = self.status {
+            *response.status_mut() = status;
+        }
+        let headers = response.headers_mut();
+        for name in &self.remove_headers {
+            headers.remove(name);
+        }
+        for (name, value) in self.set_headers {
+            headers.append(name, value);
+        }
+
+        if self.shaping.is_enabled() {
+            let shaping = self.shaping;
+            let cancellation_token = cancellation_token.clone();
+            response.map(|body| BoxBody::new(ShapedBody::new(body, shaping, cancellation_token)))
+        } else {
+            response
+        }
+    }
+}
+
+fn parse_millis(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("Invalid number of milliseconds: {value}"))
+}
+
+fn take_control_headers(headers: &mut HeaderMap) -> Vec<(HeaderName, HeaderValue)> {
+    let names: Vec<HeaderName> = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with(CONTROL_HEADER_PREFIX))
+        .cloned()
+        .collect();
+
+    let mut control_headers = Vec::new();
+    for name in names {
+        let values: Vec<HeaderValue> = headers.get_all(&name).iter().cloned().collect();
+        headers.remove(&name);
+        control_headers.extend(values.into_iter().map(|value| (name.clone(), value)));
+    }
+    control_headers
+}
+
+pub(in crate::service) fn bad_request(message: String) -> EchoResponse {
+    let response = Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Full::new(Bytes::from(message)))
+        .unwrap();
+    to_boxed_body(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::{Request, StatusCode};
+
+    use super::ResponseControl;
+
+    const MAX_DELAY: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn control_is_parsed_and_headers_are_stripped() {
+        let mut request = Request::builder()
+            .uri("/path?echo_status=404&echo_delay=10&status=active")
+            .header("x-echo-status", "503")
+            .header("x-echo-set-header", "x-first: 1")
+            .header("x-echo-set-header", "x-second: 2")
+            .header("x-echo-remove-header", "accept")
+            .header("accept", "*/*")
+            .body(())
+            .unwrap();
+
+        let control =
+            ResponseControl::from_request(&mut request, &Default::default(), MAX_DELAY).unwrap();
+
+        assert_eq!(control.status, Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(control.delay, Some(Duration::from_millis(10)));
+        assert_eq!(control.set_headers.len(), 2);
+        assert_eq!(control.remove_headers.len(), 1);
+        assert_eq!(request.headers().len(), 1);
+        assert!(request.headers().contains_key("accept"));
+    }
+
+    #[test]
+    fn invalid_control_is_rejected() {
+        let mut request = Request::builder()
+            .header("x-echo-status", "not a status")
+            .body(())
+            .unwrap();
+        assert!(
+            ResponseControl::from_request(&mut request, &Default::default(), MAX_DELAY).is_err()
+        );
+
+        let mut request = Request::builder()
+            .header("x-echo-status", "101")
+            .body(())
+            .unwrap();
+        assert!(
+            ResponseControl::from_request(&mut request, &Default::default(), MAX_DELAY).is_err()
+        );
+
+        let mut request = Request::builder()
+            .uri("/?echo_set_header=no_colon")
+            .body(())
+            .unwrap();
+        assert!(
+            ResponseControl::from_request(&mut request, &Default::default(), MAX_DELAY).is_err()
+        );
+    }
+
+    #[test]
+    fn delay_over_limit_is_rejected() {
+        let mut request = Request::builder()
+            .header("x-echo-delay-ms", "1001")
+            .body(())
+            .unwrap();
+        assert!(
+            ResponseControl::from_request(&mut request, &Default::default(), MAX_DELAY).is_err()
+        );
+
+        let mut request = Request::builder()
+            .uri("/?echo_delay=1000")
+            .body(())
+            .unwrap();
+        assert!(
+            ResponseControl::from_request(&mut request, &Default::default(), MAX_DELAY).is_ok()
+        );
+    }
+}
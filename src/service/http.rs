@@ -7,11 +7,9 @@ use hyper::{
     body::{Body, Bytes},
 };
 
-use super::BoxedError;
+use super::{BoxedError, EchoResponse};
 
-pub(in crate::service) fn echo<B>(
-    request: Request<B>,
-) -> Result<Response<BoxBody<Bytes, BoxedError!()>>, Infallible>
+pub(in crate::service) fn echo<B>(request: Request<B>) -> Result<EchoResponse, Infallible>
 where
     B: Body<Data = Bytes> + Send + Sync + 'static,
     B::Error: Error + Send + Sync + 'static,
@@ -0,0 +1,149 @@
+use std::convert::Infallible;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    HeaderMap, Request, Response, StatusCode,
+    body::{Body, Bytes},
+    header::CONTENT_TYPE,
+};
+use serde_json::{Map, Value, json};
+
+use super::{EchoResponse, http::to_boxed_body};
+use crate::connection_info::{ClientAddr, ConnectionInfo};
+
+pub(in crate::service) const INSPECT_PATH: &str = "/inspect";
+
+pub(in crate::service) fn is_inspect_request<B>(request: &Request<B>) -> bool {
+    let path = request.uri().path();
+    path.strip_prefix(INSPECT_PATH)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Respond with a JSON document describing the received request
+pub(in crate::service) async fn inspect<B>(
+    request: Request<B>,
+    connection_info: &ConnectionInfo,
+) -> Result<EchoResponse, Infallible>
+where
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let (parts, body) = request.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            return Ok(json_response(
+                StatusCode::BAD_REQUEST,
+                parts.version,
+                json!({ "error": format!("Failed to read request body: {e}") }),
+            ));
+        }
+    };
+
+    let (body_encoding, body) = encode_body(&body);
+    #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+    let mut document = json!({
+        "method": parts.method.as_str(),
+        "uri": parts.uri.to_string(),
+        "path": parts.uri.path(),
+        "query": query_to_json(parts.uri.query()),
+        "version": format!("{:?}", parts.version),
+        "headers": headers_to_json(&parts.headers),
+        "client": client_to_json(&connection_info.client_addr),
+        "connection_id": connection_info.id,
+        "body": body,
+        "body_encoding": body_encoding,
+    });
+
+    #[cfg(feature = "tls")]
+    if let Some(client_cert) = &connection_info.client_cert {
+        document["client_cert"] = json!({
+            "subject": client_cert.subject,
+            "subject_alt_names": client_cert.subject_alt_names,
+            "fingerprint": client_cert.fingerprint,
+        });
+    }
+
+    Ok(json_response(StatusCode::OK, parts.version, document))
+}
+
+fn client_to_json(client_addr: &ClientAddr) -> Value {
+    match client_addr {
+        ClientAddr::Ip(addr) => json!({
+            "ip": addr.ip().to_string(),
+            "port": addr.port(),
+        }),
+        #[cfg(unix)]
+        ClientAddr::Unix(credentials) => json!({
+            "pid": credentials.pid,
+            "uid": credentials.uid,
+            "gid": credentials.gid,
+        }),
+    }
+}
+
+fn query_to_json(query: Option<&str>) -> Value {
+    let mut params = Map::new();
+    for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        push_value(&mut params, &name, Value::from(value.into_owned()));
+    }
+    Value::Object(params)
+}
+
+fn headers_to_json(headers: &HeaderMap) -> Value {
+    let mut result = Map::new();
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+        push_value(&mut result, name.as_str(), Value::from(value));
+    }
+    Value::Object(result)
+}
+
+/// Add a value to the array stored by `name` so repeated values are preserved
+fn push_value(map: &mut Map<String, Value>, name: &str, value: Value) {
+    match map.get_mut(name) {
+        Some(Value::Array(values)) => values.push(value),
+        _ => {
+            map.insert(name.to_string(), Value::Array(vec![value]));
+        }
+    }
+}
+
+/// Returns encoding name and encoded body: UTF-8 text as is, anything else as base64
+fn encode_body(body: &Bytes) -> (&'static str, String) {
+    match std::str::from_utf8(body) {
+        Ok(text) => ("utf-8", text.to_string()),
+        Err(_) => ("base64", STANDARD.encode(body)),
+    }
+}
+
+pub(in crate::service) fn json_response(
+    status: StatusCode,
+    version: hyper::Version,
+    document: Value,
+) -> EchoResponse {
+    let response = Response::builder()
+        .status(status)
+        .version(version)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(document.to_string())))
+        .unwrap();
+    to_boxed_body(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+
+    #[test]
+    fn inspect_path_detection() {
+        let is_inspect =
+            |uri: &str| super::is_inspect_request(&Request::builder().uri(uri).body(()).unwrap());
+
+        assert!(is_inspect("/inspect"));
+        assert!(is_inspect("/inspect/some/path?a=b"));
+        assert!(!is_inspect("/inspection"));
+        assert!(!is_inspect("/"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use http_body_util::{Full, combinators::BoxBody};
+use hyper::{
+    Request, Response,
+    body::{Body, Bytes, Frame},
+    header::CONTENT_TYPE,
+};
+use pin_project::pin_project;
+use tower::{Layer, Service};
+
+use super::{BoxedError, EchoResponse, http::to_boxed_body};
+use crate::metrics::{METRICS_PATH, Metrics};
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub(in crate::service) fn is_metrics_request<B>(request: &Request<B>) -> bool {
+    request.uri().path() == METRICS_PATH
+}
+
+pub(in crate::service) fn metrics_response(metrics: &Metrics) -> EchoResponse {
+    let response = Response::builder()
+        .header(CONTENT_TYPE, METRICS_CONTENT_TYPE)
+        .body(Full::new(Bytes::from(metrics.render())))
+        .unwrap();
+    to_boxed_body(response)
+}
+
+/// Layer recording HTTP request metrics
+#[derive(Debug, Clone)]
+pub(in crate::service) struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(in crate::service) struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S, B> Service<Request<B>> for MetricsService<S>
+where
+    S: Service<Request<CountingBody<B>>, Response = EchoResponse, Error = BoxedError!()>,
+    S::Future: Send + 'static,
+{
+    type Response = EchoResponse;
+    type Error = BoxedError!();
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let start = Instant::now();
+        let method = request.method().clone();
+        let version = request.version();
+        let metrics = self.metrics.clone();
+
+        let request = request
+            .map(|body| CountingBody::new(body, metrics.clone(), Metrics::add_request_bytes));
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await?;
+            metrics.record_http_request(&method, response.status(), version, start.elapsed());
+            Ok(response.map(|body| {
+                BoxBody::new(CountingBody::new(
+                    body,
+                    metrics,
+                    Metrics::add_response_bytes,
+                ))
+            }))
+        })
+    }
+}
+
+/// Body passing through the inner body and counting its data bytes
+#[pin_project]
+pub(in crate::service) struct CountingBody<B> {
+    #[pin]
+    inner: B,
+    metrics: Arc<Metrics>,
+    count: fn(&Metrics, usize),
+}
+
+impl<B> CountingBody<B> {
+    fn new(inner: B, metrics: Arc<Metrics>, count: fn(&Metrics, usize)) -> Self {
+        Self {
+            inner,
+            metrics,
+            count,
+        }
+    }
+}
+
+impl<B> Body for CountingBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = this.inner.poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame
+            && let Some(data) = frame.data_ref()
+        {
+            (this.count)(this.metrics, data.len());
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    future::Future,
+    io::Error,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    task::{Context, Poll, ready},
+};
+
+use http_body_util::Full;
+use hyper::{
+    Request, Response, StatusCode,
+    body::{Body, Bytes, Frame},
+    header::CONTENT_LENGTH,
+};
+use pin_project::pin_project;
+use tokio::time::{Instant, Sleep, sleep_until, timeout_at};
+use tower::{Layer, Service};
+
+use super::{BoxedError, EchoResponse, http::to_boxed_body};
+use crate::limits::RequestLimits;
+
+const BODY_RECEIVING: u8 = 0;
+const BODY_RECEIVED: u8 = 1;
+const BODY_TIMED_OUT: u8 = 2;
+
+/// Layer enforcing [RequestLimits] on requests
+#[derive(Debug, Clone)]
+pub(in crate::service) struct RequestLimitsLayer {
+    limits: RequestLimits,
+}
+
+impl RequestLimitsLayer {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self { limits }
+    }
+}
+
+impl<S> Layer<S> for RequestLimitsLayer {
+    type Service = RequestLimitsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLimitsService {
+            inner,
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(in crate::service) struct RequestLimitsService<S> {
+    inner: S,
+    limits: RequestLimits,
+}
+
+impl<S, B> Service<Request<B>> for RequestLimitsService<S>
+where
+    B: Body,
+    S: Service<Request<LimitedBody<B>>, Response = EchoResponse, Error = BoxedError!()>,
+    S::Future: Send + 'static,
+{
+    type Response = EchoResponse;
+    type Error = BoxedError!();
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        if let Some(max_body_size) = self.limits.max_body_size
+            && content_length(&request).is_some_and(|length| length > max_body_size)
+        {
+            let response = error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Request body is larger than {max_body_size} bytes"),
+            );
+            return Box::pin(async { Ok(response) });
+        }
+
+        let deadline = self
+            .limits
+            .request_timeout
+            .map(|timeout| Instant::now() + timeout);
+        let body_state = Arc::new(AtomicU8::new(BODY_RECEIVING));
+        let request = request.map(|body| {
+            LimitedBody::new(
+                body,
+                self.limits.max_body_size,
+                deadline,
+                body_state.clone(),
+            )
+        });
+        let future = self.inner.call(request);
+
+        let Some(deadline) = deadline else {
+            return Box::pin(future);
+        };
+        Box::pin(async move {
+            let response = timeout_at(deadline, future).await;
+            match (response, body_state.load(Ordering::Relaxed)) {
+                // Body reading might fail before the timeout fires
+                (_, BODY_TIMED_OUT) | (Err(_), BODY_RECEIVING) => Ok(error_response(
+                    StatusCode::REQUEST_TIMEOUT,
+                    "Request was not received in time".to_string(),
+                )),
+                (Ok(response), _) => response,
+                (Err(_), _) => Ok(error_response(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "Response was not ready in time".to_string(),
+                )),
+            }
+        })
+    }
+}
+
+fn content_length<B>(request: &Request<B>) -> Option<usize> {
+    request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+fn error_response(status: StatusCode, message: String) -> EchoResponse {
+    let response = Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from(message)))
+        .unwrap();
+    to_boxed_body(response)
+}
+
+/// Request body failing once it exceeds the size limit or is not received before the deadline
+#[pin_project]
+pub(in crate::service) struct LimitedBody<B> {
+    #[pin]
+    inner: B,
+    remaining_bytes: Option<usize>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    state: Arc<AtomicU8>,
+}
+
+impl<B> LimitedBody<B> {
+    fn new(
+        inner: B,
+        max_size: Option<usize>,
+        deadline: Option<Instant>,
+        state: Arc<AtomicU8>,
+    ) -> Self
+    where
+        B: Body,
+    {
+        if inner.is_end_stream() {
+            state.store(BODY_RECEIVED, Ordering::Relaxed);
+        }
+        Self {
+            inner,
+            remaining_bytes: max_size,
+            deadline: deadline.map(|deadline| Box::pin(sleep_until(deadline))),
+            state,
+        }
+    }
+}
+
+impl<B> Body for LimitedBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = match this.inner.poll_frame(cx) {
+            Poll::Ready(frame) => frame,
+            Poll::Pending => {
+                if let Some(deadline) = this.deadline.as_mut() {
+                    ready!(deadline.as_mut().poll(cx));
+                    this.state.store(BODY_TIMED_OUT, Ordering::Relaxed);
+                    return Poll::Ready(Some(Err(Error::other(
+                        "Request body was not received in time",
+                    ))));
+                }
+                return Poll::Pending;
+            }
+        };
+
+        match frame {
+            Some(Ok(frame)) => {
+                if let (Some(remaining_bytes), Some(data)) =
+                    (this.remaining_bytes, frame.data_ref())
+                {
+                    if data.len() > *remaining_bytes {
+                        return Poll::Ready(Some(Err(Error::other(
+                            "Request body is larger than allowed",
+                        ))));
+                    }
+                    *remaining_bytes -= data.len();
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(Error::other(e)))),
+            None => {
+                this.state.store(BODY_RECEIVED, Ordering::Relaxed);
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use hyper::body::{Body, Bytes, Frame};
+use pin_project::pin_project;
+use tokio::time::Sleep;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+/// Number of pieces a second of throttled traffic is split into to keep the stream smooth
+const PIECES_PER_SECOND: u64 = 10;
+
+/// Traffic shaping settings of an [EchoServer](crate::EchoServer).
+///
+/// Applies to echoed HTTP response bodies and, if `websocket` is set, to echoed WebSocket messages.
+/// Could be overridden for a single request with request headers:
+/// - `x-echo-rate-bps: N` - bandwidth limit in bytes per second
+/// - `x-echo-chunk-latency-ms: N` - latency added to each chunk
+/// - `x-echo-jitter-ms: N` - max random latency added to each chunk
+///
+/// or with `echo_rate`, `echo_latency` and `echo_jitter` query parameters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShapingConfig {
+    /// Bandwidth limit in bytes per second or `None` for unlimited
+    pub bytes_per_second: Option<u64>,
+    /// Latency added to each chunk of a body or each WebSocket message
+    pub chunk_latency: Duration,
+    /// Max random latency added on top of `chunk_latency`
+    pub jitter: Duration,
+    /// Whether to shape echoed WebSocket messages
+    pub websocket: bool,
+}
+
+impl ShapingConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.bytes_per_second == Some(0) {
+            return Err("Bandwidth limit must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    pub(in crate::service) fn is_enabled(&self) -> bool {
+        self.bytes_per_second.is_some() || !self.chunk_latency.is_zero() || !self.jitter.is_zero()
+    }
+
+    /// Delay before sending a chunk of `len` bytes
+    pub(in crate::service) fn delay_for(&self, len: usize) -> Duration {
+        self.latency() + self.transfer_time(len)
+    }
+
+    /// Latency of a chunk with random jitter
+    fn latency(&self) -> Duration {
+        let jitter = if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            self.jitter.mul_f64(rand::random::<f64>())
+        };
+        self.chunk_latency + jitter
+    }
+
+    /// Time to transfer `len` bytes with the bandwidth limit
+    fn transfer_time(&self, len: usize) -> Duration {
+        self.bytes_per_second
+            .map(|bps| Duration::from_secs_f64(len as f64 / bps as f64))
+            .unwrap_or_default()
+    }
+
+    fn max_piece_len(&self) -> usize {
+        self.bytes_per_second
+            .map(|bps| (bps / PIECES_PER_SECOND).max(1) as usize)
+            .unwrap_or(usize::MAX)
+    }
+}
+
+/// Body sending data of the inner body with delays according to [ShapingConfig].
+/// Delays are skipped once the server is stopped.
+#[pin_project]
+pub(in crate::service) struct ShapedBody<B> {
+    #[pin]
+    inner: B,
+    config: ShapingConfig,
+    pending: Bytes,
+    /// Whether the latency of the pending chunk is already applied
+    latency_applied: bool,
+    ready: Option<Bytes>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    #[pin]
+    cancelled: WaitForCancellationFutureOwned,
+}
+
+impl<B> ShapedBody<B> {
+    pub fn new(inner: B, config: ShapingConfig, cancellation_token: CancellationToken) -> Self {
+        Self {
+            inner,
+            config,
+            pending: Bytes::new(),
+            latency_applied: false,
+            ready: None,
+            sleep: None,
+            cancelled: cancellation_token.cancelled_owned(),
+        }
+    }
+}
+
+impl<B> Body for ShapedBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if let Some(sleep) = this.sleep.as_mut() {
+                if this.cancelled.as_mut().poll(cx).is_pending() {
+                    ready!(sleep.as_mut().poll(cx));
+                }
+                *this.sleep = None;
+                let data = this.ready.take().unwrap_or_default();
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+
+            if !this.pending.is_empty() {
+                let len = this.pending.len().min(this.config.max_piece_len());
+                let piece = this.pending.split_to(len);
+                // A chunk split into pieces to smooth the bandwidth gets the latency only once
+                let mut delay = this.config.transfer_time(len);
+                if !*this.latency_applied {
+                    *this.latency_applied = true;
+                    delay += this.config.latency();
+                }
+                *this.sleep = Some(Box::pin(tokio::time::sleep(delay)));
+                *this.ready = Some(piece);
+                continue;
+            }
+
+            match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        *this.pending = data;
+                        *this.latency_applied = false;
+                    }
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return Poll::Ready(other),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ShapingConfig;
+
+    #[test]
+    fn delay_for_chunk() {
+        let config = ShapingConfig {
+            bytes_per_second: Some(1000),
+            chunk_latency: Duration::from_millis(10),
+            ..Default::default()
+        };
+        assert_eq!(config.delay_for(100), Duration::from_millis(110));
+        assert_eq!(config.max_piece_len(), 100);
+
+        let config = ShapingConfig {
+            jitter: Duration::from_millis(10),
+            ..Default::default()
+        };
+        assert!(config.delay_for(100) <= Duration::from_millis(10));
+        assert!(config.is_enabled());
+        assert!(!ShapingConfig::default().is_enabled());
+    }
+}
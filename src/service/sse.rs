@@ -0,0 +1,278 @@
+use std::{
+    convert::Infallible,
+    fmt::Write,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http_body_util::BodyExt;
+use hyper::{
+    Request, Response,
+    body::{Body, Bytes, Frame},
+    header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE},
+};
+use tokio::{
+    select,
+    sync::mpsc,
+    time::{Instant, interval_at},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{EchoResponse, control::bad_request, http::to_boxed_body};
+
+const SSE_PATH: &str = "/sse";
+const EVENT_STREAM: &str = "text/event-stream";
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+const INTERVAL_PARAM: &str = "interval";
+const COUNT_PARAM: &str = "count";
+const ECHO_EVENT: &str = "echo";
+const TICK_EVENT: &str = "tick";
+/// Max number of events waiting to be sent to a slow client
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Server-Sent Events settings of an [EchoServer](crate::EchoServer).
+///
+/// Requests to `/sse` or with `Accept: text/event-stream` are answered with an event stream:
+/// each line of the request body is sent back as an `echo` event, then `tick` events with ids
+/// starting from 1 are sent every `interval`. A client reconnecting with `Last-Event-ID: N`
+/// receives tick events starting from `N + 1`.
+///
+/// Could be overridden for a single request with `interval` (in milliseconds) and `count` query parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseConfig {
+    /// Interval between tick events
+    pub interval: Duration,
+    /// Id of the last tick event after which the stream ends or `None` to stream until the client disconnects
+    pub count: Option<u64>,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            count: None,
+        }
+    }
+}
+
+impl SseConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.interval.is_zero() {
+            return Err("SSE interval must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// Server settings overridden by the query parameters of the request
+    fn for_request<B>(&self, request: &Request<B>) -> Result<Self, String> {
+        let mut config = self.clone();
+        let query = request.uri().query().unwrap_or_default();
+        for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+            let parse = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid {name} parameter {value}"))
+            };
+            match name.as_ref() {
+                INTERVAL_PARAM => config.interval = Duration::from_millis(parse()?),
+                COUNT_PARAM => config.count = Some(parse()?),
+                _ => {}
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub(in crate::service) fn is_sse_request<B>(request: &Request<B>) -> bool {
+    let path = request.uri().path();
+    let is_sse_path = path
+        .strip_prefix(SSE_PATH)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+    let accepts_event_stream = request
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains(EVENT_STREAM));
+    is_sse_path || accepts_event_stream
+}
+
+/// Respond with an event stream echoing the request body and sending periodic tick events
+/// until the count is reached, the client disconnects or the server is stopped
+pub(in crate::service) async fn stream<B>(
+    request: Request<B>,
+    config: &SseConfig,
+    cancellation_token: CancellationToken,
+) -> Result<EchoResponse, Infallible>
+where
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let config = match config.for_request(&request) {
+        Ok(config) => config,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let last_event_id = match request.headers().get(LAST_EVENT_ID_HEADER) {
+        Some(value) => match value
+            .to_str()
+            .ok()
+            .and_then(|id| id.trim().parse::<u64>().ok())
+        {
+            Some(id) => id,
+            None => return Ok(bad_request(format!("Invalid Last-Event-ID {value:?}"))),
+        },
+        None => 0,
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => return Ok(bad_request(format!("Failed to read request body: {e}"))),
+    };
+    let echo_events = String::from_utf8_lossy(&body)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| event(ECHO_EVENT, None, line))
+        .collect();
+
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(send_events(
+        sender,
+        echo_events,
+        config,
+        last_event_id + 1,
+        cancellation_token,
+    ));
+
+    let response = Response::builder()
+        .status(200)
+        .version(parts.version)
+        .header(CONTENT_TYPE, EVENT_STREAM)
+        .header(CACHE_CONTROL, "no-cache")
+        .body(SseBody { receiver })
+        .unwrap();
+    Ok(to_boxed_body(response))
+}
+
+async fn send_events(
+    sender: mpsc::Sender<Bytes>,
+    echo_events: Vec<Bytes>,
+    config: SseConfig,
+    first_id: u64,
+    cancellation_token: CancellationToken,
+) {
+    for event in echo_events {
+        if sender.send(event).await.is_err() {
+            return;
+        }
+    }
+
+    let mut interval = interval_at(Instant::now() + config.interval, config.interval);
+    let mut id = first_id;
+    while config.count.is_none_or(|count| id <= count) {
+        select! {
+            _ = interval.tick() => {},
+            _ = sender.closed() => return,
+            _ = cancellation_token.cancelled() => return,
+        }
+        if sender
+            .send(event(TICK_EVENT, Some(id), &id.to_string()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        id += 1;
+    }
+}
+
+/// Format an event, `data` could contain several lines
+fn event(name: &str, id: Option<u64>, data: &str) -> Bytes {
+    let mut event = format!("event: {name}\n");
+    if let Some(id) = id {
+        writeln!(event, "id: {id}").unwrap();
+    }
+    for line in data.split('\n') {
+        writeln!(event, "data: {line}").unwrap();
+    }
+    event.push('\n');
+    Bytes::from(event)
+}
+
+/// Body sending events until the sending task finishes
+struct SseBody {
+    receiver: mpsc::Receiver<Bytes>,
+}
+
+impl Body for SseBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.receiver
+            .poll_recv(cx)
+            .map(|event| event.map(|event| Ok(Frame::data(event))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::Request;
+
+    use super::SseConfig;
+
+    #[test]
+    fn sse_request_detection() {
+        let is_sse = |uri: &str, accept: &str| {
+            let request = Request::builder()
+                .uri(uri)
+                .header("accept", accept)
+                .body(())
+                .unwrap();
+            super::is_sse_request(&request)
+        };
+
+        assert!(is_sse("/sse", "*/*"));
+        assert!(is_sse("/sse/stream?count=1", "*/*"));
+        assert!(is_sse("/any", "text/event-stream"));
+        assert!(!is_sse("/ssebox", "*/*"));
+        assert!(!is_sse("/", "text/html, */*"));
+    }
+
+    #[test]
+    fn config_is_overridden_by_query() {
+        let config = SseConfig::default();
+        let for_request =
+            |uri: &str| config.for_request(&Request::builder().uri(uri).body(()).unwrap());
+
+        assert_eq!(
+            for_request("/sse?interval=100&count=5"),
+            Ok(SseConfig {
+                interval: Duration::from_millis(100),
+                count: Some(5)
+            })
+        );
+        assert_eq!(for_request("/sse"), Ok(config.clone()));
+        assert!(for_request("/sse?interval=0").is_err());
+        assert!(for_request("/sse?count=many").is_err());
+    }
+
+    #[test]
+    fn events_are_formatted() {
+        assert_eq!(
+            super::event("tick", Some(3), "3"),
+            "event: tick\nid: 3\ndata: 3\n\n"
+        );
+        assert_eq!(
+            super::event("echo", None, "a\nb"),
+            "event: echo\ndata: a\ndata: b\n\n"
+        );
+    }
+}
@@ -1,143 +1,676 @@
 use std::time::Duration;
-use std::{convert::Infallible, time::Instant};
+use std::{borrow::Cow, convert::Infallible, str::FromStr, sync::Arc, time::Instant};
 
 use fastwebsockets::{
-    CloseCode, Frame, OpCode, Payload, WebSocket, WebSocketError, upgrade::upgrade,
+    CloseCode, Frame, OpCode, Payload, Role, WebSocket, WebSocketError, WebSocketRead,
+    WebSocketWrite,
+    upgrade::{UpgradeFut, upgrade},
+};
+use http_body_util::{Empty, Full};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::Bytes,
+    ext::Protocol,
+    header::{
+        HeaderValue, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION,
+    },
+    upgrade::{OnUpgrade, Upgraded},
 };
-use http_body_util::Full;
-use http_body_util::combinators::BoxBody;
-use hyper::StatusCode;
-use hyper::{Request, Response, body::Bytes, upgrade::Upgraded};
 use hyper_util::rt::TokioIo;
-use tokio::{select, time::sleep};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, WriteHalf},
+    select,
+    sync::{broadcast::error::RecvError, mpsc},
+    time::{Interval, interval_at, sleep},
+};
 use tokio_util::sync::CancellationToken;
 use tracing::warn;
 
-use crate::ws_logger::WsLogger;
+use crate::{config::Config, metrics::Metrics, ws_logger::WsLogger};
+
+use super::EchoResponse;
+use super::control::bad_request;
+use deflate::{DeflateError, DeflateParams, PerMessageDeflate};
+use hub::{BroadcastMessage, RoomMember};
+use mode::{SessionEnd, WsMode};
+
+pub use deflate::DeflateConfig;
+pub(crate) use hub::WsHub;
 
-use super::BoxedError;
+mod deflate;
+mod hub;
+mod mode;
 use super::http::to_boxed_body;
 
+const INVALID_UTF8_CLOSE_REASON: &str = "Invalid UTF-8 in text message";
+const INVALID_COMPRESSED_CLOSE_REASON: &str = "Invalid compressed message";
+const FRAGMENTATION_CLOSE_REASON: &str = "Unexpected message fragment";
+const FRAGMENT_MODE_PARAM: &str = "fragments";
+
+/// How fragmented WebSocket messages are echoed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WsFragmentMode {
+    /// Reassemble the fragments and echo the whole message in a single frame
+    #[default]
+    Reassemble,
+    /// Echo every fragment as soon as it is received keeping the fragmentation of the client
+    Mirror,
+}
+
+impl FromStr for WsFragmentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reassemble" => Ok(Self::Reassemble),
+            "mirror" => Ok(Self::Mirror),
+            _ => Err(format!(
+                "Invalid fragment mode {s}, expected reassemble or mirror"
+            )),
+        }
+    }
+}
+
+/// Selection of a WebSocket subprotocol offered by a client in the `sec-websocket-protocol` header
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WsSubprotocols {
+    /// Don't select a subprotocol
+    #[default]
+    None,
+    /// Select the first offered subprotocol which is in the list
+    Supported(Vec<String>),
+    /// Select the first offered subprotocol, whatever it is
+    EchoFirst,
+}
+
+impl WsSubprotocols {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if let Self::Supported(protocols) = self
+            && let Some(protocol) = protocols.iter().find(|protocol| !is_token(protocol))
+        {
+            return Err(format!("Invalid WebSocket subprotocol name '{protocol}'"));
+        }
+        Ok(())
+    }
+
+    /// Returns the subprotocol to select for the upgrade request
+    fn select<B>(&self, request: &Request<B>) -> Option<String> {
+        let mut offered = request
+            .headers()
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|protocol| is_token(protocol));
+        match self {
+            Self::None => None,
+            Self::Supported(protocols) => {
+                offered.find(|protocol| protocols.iter().any(|p| p == protocol))
+            }
+            Self::EchoFirst => offered.next(),
+        }
+        .map(str::to_string)
+    }
+}
+
+/// Whether the string is a valid HTTP token (RFC 9110)
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Read by the reader task of a session
+enum Incoming {
+    /// Frame received from the client
+    Frame(Frame<'static>),
+    /// Pong or close frame the library requires to send in reply to a received frame
+    Reply(Frame<'static>),
+}
+
+/// Data message being received, possibly in several fragments
+struct FragmentedMessage {
+    opcode: OpCode,
+    compressed: bool,
+    payload: Vec<u8>,
+    started: Instant,
+}
+
 #[derive(Debug, Clone)]
 pub struct SessionData {
     ws_logger: WsLogger,
-    ws_ping_interval: Option<Duration>,
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    hub: Arc<WsHub>,
     cancellation_token: CancellationToken,
 }
 
 impl SessionData {
     pub fn new(
         ws_logger: WsLogger,
-        ws_ping_interval: Option<Duration>,
+        config: Arc<Config>,
+        metrics: Arc<Metrics>,
+        hub: Arc<WsHub>,
         cancellation_token: CancellationToken,
     ) -> Self {
         Self {
             ws_logger,
-            ws_ping_interval,
+            config,
+            metrics,
+            hub,
             cancellation_token,
         }
     }
 }
 
+/// Upgrade of an HTTP/1.1 connection or of an HTTP/2 stream opened by extended CONNECT (RFC 8441)
+enum PendingUpgrade {
+    Http1(UpgradeFut),
+    Http2(OnUpgrade),
+}
+
+impl PendingUpgrade {
+    async fn websocket(self) -> Result<WebSocket<TokioIo<Upgraded>>, WebSocketError> {
+        match self {
+            Self::Http1(upgrade) => upgrade.await,
+            Self::Http2(upgrade) => Ok(WebSocket::after_handshake(
+                TokioIo::new(upgrade.await?),
+                Role::Server,
+            )),
+        }
+    }
+}
+
+/// Whether the request is an HTTP/1.1 upgrade or an HTTP/2 extended CONNECT to WebSocket
+pub(in crate::service) fn is_upgrade_request<B>(request: &Request<B>) -> bool {
+    fastwebsockets::upgrade::is_upgrade_request(request) || is_h2_upgrade_request(request)
+}
+
+fn is_h2_upgrade_request<B>(request: &Request<B>) -> bool {
+    request.method() == Method::CONNECT
+        && request
+            .extensions()
+            .get::<Protocol>()
+            .is_some_and(|protocol| protocol.as_str().eq_ignore_ascii_case("websocket"))
+}
+
+/// Accept an extended CONNECT request. Unlike HTTP/1.1 there is no key to confirm, the stream
+/// becomes a WebSocket once a `200` response is sent.
+fn h2_upgrade<B>(
+    request: &mut Request<B>,
+) -> Result<(Response<Empty<Bytes>>, PendingUpgrade), WebSocketError> {
+    if request
+        .headers()
+        .get(SEC_WEBSOCKET_VERSION)
+        .is_some_and(|version| version != "13")
+    {
+        return Err(WebSocketError::InvalidSecWebsocketVersion);
+    }
+    let upgrade = hyper::upgrade::on(request);
+    Ok((Response::new(Empty::new()), PendingUpgrade::Http2(upgrade)))
+}
+
 pub(in crate::service) fn run_session<B>(
     mut request: Request<B>,
     session_data: SessionData,
-) -> Result<Response<BoxBody<Bytes, BoxedError!()>>, Infallible>
+) -> Result<EchoResponse, Infallible>
 where
     B: Send + Sync + 'static,
 {
-    match upgrade(&mut request) {
-        Ok((response, fut)) => {
+    let fragment_mode = match fragment_mode(&request, &session_data.config) {
+        Ok(fragment_mode) => fragment_mode,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let mode = match WsMode::from_request(&request) {
+        Ok(mode) => mode,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    // Join before the handshake completes to receive messages sent right after it
+    let room = match &mode {
+        WsMode::Broadcast { room } => Some(session_data.hub.join(room)),
+        _ => None,
+    };
+    let subprotocol = session_data.config.ws_subprotocols.select(&request);
+    let deflate = session_data
+        .config
+        .ws_deflate
+        .as_ref()
+        .and_then(|config| DeflateParams::negotiate(request.headers(), config))
+        .map(PerMessageDeflate::new);
+    let upgrade = if is_h2_upgrade_request(&request) {
+        h2_upgrade(&mut request)
+    } else {
+        upgrade(&mut request).map(|(response, fut)| (response, PendingUpgrade::Http1(fut)))
+    };
+    match upgrade {
+        Ok((mut response, pending_upgrade)) => {
+            if let Some(protocol) = &subprotocol {
+                session_data.ws_logger.record_subprotocol(protocol);
+                let protocol = HeaderValue::from_str(protocol).unwrap();
+                response
+                    .headers_mut()
+                    .insert(SEC_WEBSOCKET_PROTOCOL, protocol);
+            }
+            if let Some(deflate) = &deflate {
+                let extensions = HeaderValue::from_str(&deflate.params().to_string()).unwrap();
+                response
+                    .headers_mut()
+                    .insert(SEC_WEBSOCKET_EXTENSIONS, extensions);
+            }
             tokio::spawn(async move {
-                match fut.await {
-                    Ok(mut ws) => {
-                        ws.set_auto_close(true);
-                        ws.set_auto_pong(true);
-                        ws.set_max_message_size(16 * 1024 * 1024); // 16 MB
-                        echo_ws(ws, session_data).await;
+                match (pending_upgrade.websocket().await, deflate) {
+                    (Ok(ws), Some(deflate)) => {
+                        let io = deflate.wrap(ws.into_inner());
+                        let ws = WebSocket::after_handshake(io, Role::Server);
+                        let deflate = Some(deflate);
+                        echo_ws(ws, session_data, mode, room, fragment_mode, deflate).await;
+                    }
+                    (Ok(ws), None) => {
+                        echo_ws(ws, session_data, mode, room, fragment_mode, None).await;
                     }
-                    Err(e) => {
+                    (Err(e), _) => {
+                        session_data.metrics.record_ws_upgrade_failure();
                         warn!("Failed to establish websocket connection: {e}");
                     }
                 }
             });
             Ok(to_boxed_body(response))
         }
-        Err(e) => Ok(to_response(e)),
+        Err(e) => {
+            session_data.metrics.record_ws_upgrade_failure();
+            Ok(to_response(e))
+        }
     }
 }
 
-async fn echo_ws(mut ws: WebSocket<TokioIo<Upgraded>>, session_data: SessionData) {
-    let mut ping_interval =
-        tokio::time::interval(session_data.ws_ping_interval.unwrap_or(Duration::MAX));
-    let mut got_pong: Option<bool> = None;
+/// Fragment mode requested by the `fragments` query parameter or the server default
+fn fragment_mode<B>(request: &Request<B>, config: &Config) -> Result<WsFragmentMode, String> {
+    let query = request.uri().query().unwrap_or_default();
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == FRAGMENT_MODE_PARAM)
+        .map_or(Ok(config.ws_fragment_mode), |(_, value)| value.parse())
+}
 
+async fn echo_ws<S>(
+    mut ws: WebSocket<S>,
+    session_data: SessionData,
+    mode: WsMode,
+    mut room: Option<RoomMember>,
+    fragment_mode: WsFragmentMode,
+    mut deflate: Option<PerMessageDeflate>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let config = &session_data.config;
+    ws.set_auto_close(true);
+    ws.set_auto_pong(config.ws_auto_pong);
+    // The library rejects frames with payload size equal to the limit
+    let max_frame_size = config
+        .ws_max_frame_size
+        .unwrap_or(config.ws_max_message_size);
+    ws.set_max_message_size(max_frame_size.saturating_add(1));
+    // Reading a frame is not cancel safe, so frames are read by a separate task
+    // and the session waits for them together with pings, broadcasts and periodic messages
+    let (reader, mut ws) = ws.split(tokio::io::split);
+    let (incoming_sender, mut incoming) = mpsc::channel(1);
+    let reader = tokio::spawn(read_frames(reader, incoming_sender));
+
+    let mut ping_interval = config.ws_ping_interval.map(tokio::time::interval);
+    let pong_timeout = config.ws_pong_timeout.or(config.ws_ping_interval);
+    let mut pong_deadline: Option<tokio::time::Instant> = None;
+    let mut close_code: u16 = CloseCode::Normal.into();
+    let mut close_reason = "";
+    let mut message: Option<FragmentedMessage> = None;
+    let fragment_mode = if mode.needs_whole_messages() {
+        WsFragmentMode::Reassemble
+    } else {
+        fragment_mode
+    };
+    let mut messages = 0_u64;
+    let mut drop_connection = false;
+    let (mut periodic_interval, periodic_count) = match mode {
+        WsMode::Periodic { interval, count } => (
+            Some(interval_at(
+                tokio::time::Instant::now() + interval,
+                interval,
+            )),
+            count,
+        ),
+        _ => (None, None),
+    };
+    let mut periodic_sent = 0_u64;
+
+    let _active_session = session_data.metrics.ws_session_started();
     session_data.ws_logger.log_connection_established();
+    if let Some(deflate) = &deflate {
+        session_data
+            .ws_logger
+            .log_extensions(&deflate.params().to_string());
+    }
+    if let Some(room) = &room {
+        session_data
+            .ws_logger
+            .log_room_joined(room.room(), room.members_on_join());
+    }
     loop {
+        match mode.session_end(messages) {
+            Some(SessionEnd::Close { code, reason }) => {
+                session_data
+                    .ws_logger
+                    .log(&format!("Closing session after {messages} messages"));
+                close_code = code;
+                close_reason = reason;
+                break;
+            }
+            Some(SessionEnd::Drop) => {
+                session_data
+                    .ws_logger
+                    .log(&format!("Dropping connection after {messages} messages"));
+                drop_connection = true;
+                break;
+            }
+            None => {}
+        }
+
         let frame = select! {
             biased;
-            _ = ping_interval.tick() => {
-                if session_data.ws_ping_interval.is_none() {
-                    continue;
-                }
-                if let Some(false) = got_pong {
-                    session_data.ws_logger.log("Didn't receive pong from client");
-                    break;
-                }
+            _ = wait_deadline(pong_deadline) => {
+                session_data.metrics.record_ws_ping_timeout();
+                session_data.ws_logger.log("Didn't receive pong from client");
+                break;
+            },
+            ping_time = tick(&mut ping_interval) => {
                 let ping_frame = Frame::new(true, OpCode::Ping, None, Payload::Borrowed(&[]));
                 if ws.write_frame(ping_frame).await.is_err() {
                     break;
                 }
-                got_pong = Some(false);
+                if pong_deadline.is_none() {
+                    pong_deadline = pong_timeout.map(|timeout| ping_time + timeout);
+                }
                 continue;
             },
-            frame = session_data.cancellation_token.run_until_cancelled(ws.read_frame()) => {
-                    let Some(Ok(frame)) = frame else {break;};
-                    frame
+            _ = tick(&mut periodic_interval) => {
+                periodic_sent += 1;
+                if periodic_count.is_some_and(|count| periodic_sent >= count) {
+                    periodic_interval = None;
+                }
+                let payload = format!("message {periodic_sent}").into_bytes();
+                let frame = outgoing_frame(OpCode::Text, payload, &mut deflate);
+                if !echo(&mut ws, frame, &session_data).await {
+                    break;
+                }
+                continue;
+            },
+            broadcast_message = receive(&mut room, config.ws_broadcast_to_sender) => {
+                match broadcast_message {
+                    Ok(BroadcastMessage { opcode, payload, .. }) => {
+                        let frame = outgoing_frame(opcode, payload.to_vec(), &mut deflate);
+                        if !echo(&mut ws, frame, &session_data).await {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        session_data
+                            .ws_logger
+                            .log(&format!("Skipped {skipped} broadcast messages"));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+                continue;
+            },
+            incoming = session_data.cancellation_token.run_until_cancelled(incoming.recv()) => {
+                match incoming.flatten() {
+                    Some(Ok(Incoming::Frame(frame))) => frame,
+                    Some(Ok(Incoming::Reply(frame))) => {
+                        if !ws.is_closed() && ws.write_frame(frame).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Some(Err(WebSocketError::FrameTooLarge)) => {
+                        session_data.ws_logger.log("Received frame is too large");
+                        close_code = config.ws_limit_close_code;
+                        close_reason = &config.ws_limit_close_reason;
+                        break;
+                    }
+                    Some(Err(WebSocketError::InvalidUTF8)) => {
+                        session_data
+                            .ws_logger
+                            .log("Received text message is not valid UTF-8");
+                        close_code = CloseCode::Invalid.into();
+                        close_reason = INVALID_UTF8_CLOSE_REASON;
+                        break;
+                    }
+                    _ => break,
+                }
             },
         };
 
-        let start = Instant::now();
+        let inbound = match (frame.opcode, &deflate) {
+            (OpCode::Text | OpCode::Binary, Some(deflate)) => deflate.next_inbound_frame(),
+            _ => None,
+        };
+        // Compressed frames are read as binary, the original opcode is tracked by deflate
+        let frame_opcode = inbound.map_or(frame.opcode, |inbound| inbound.opcode);
+        session_data.metrics.record_ws_frame(frame_opcode);
         match frame.opcode {
-            OpCode::Text | OpCode::Binary => {
-                let payload = String::from_utf8_lossy(&frame.payload);
-                session_data.ws_logger.log(&payload);
-                let frame = Frame::new(
-                    true,
-                    frame.opcode,
-                    None,
-                    Payload::Borrowed(payload.as_bytes()),
-                );
-                if let Err(e) = ws.write_frame(frame).await {
+            OpCode::Text | OpCode::Binary | OpCode::Continuation => {
+                let opcode = match (frame_opcode, &message) {
+                    (OpCode::Continuation, Some(message)) => message.opcode,
+                    (OpCode::Continuation, None) | (_, Some(_)) => {
+                        session_data
+                            .ws_logger
+                            .log("Received frame breaks message fragmentation");
+                        close_code = CloseCode::Protocol.into();
+                        close_reason = FRAGMENTATION_CLOSE_REASON;
+                        break;
+                    }
+                    (opcode, None) => opcode,
+                };
+                let current = message.get_or_insert_with(|| FragmentedMessage {
+                    opcode,
+                    compressed: inbound.is_some_and(|inbound| inbound.compressed),
+                    payload: Vec::new(),
+                    started: Instant::now(),
+                });
+                let limit = config.ws_max_message_size - current.payload.len();
+                let payload = match deflate.as_mut().filter(|_| current.compressed) {
+                    Some(deflate) => match deflate.decompress(&frame.payload, frame.fin, limit) {
+                        Ok(payload) => Cow::Owned(payload),
+                        Err(DeflateError::TooLarge) => {
+                            session_data.ws_logger.log("Received message is too large");
+                            close_code = config.ws_limit_close_code;
+                            close_reason = &config.ws_limit_close_reason;
+                            break;
+                        }
+                        Err(DeflateError::Invalid) => {
+                            session_data
+                                .ws_logger
+                                .log("Received message could not be decompressed");
+                            close_code = CloseCode::Invalid.into();
+                            close_reason = INVALID_COMPRESSED_CLOSE_REASON;
+                            break;
+                        }
+                    },
+                    None => Cow::Borrowed(&frame.payload[..]),
+                };
+                if payload.len() > limit {
+                    session_data.ws_logger.log("Received message is too large");
+                    close_code = config.ws_limit_close_code;
+                    close_reason = &config.ws_limit_close_reason;
+                    break;
+                }
+                current.payload.extend_from_slice(&payload);
+                if frame.fin
+                    && opcode == OpCode::Text
+                    && std::str::from_utf8(&current.payload).is_err()
+                {
                     session_data
                         .ws_logger
-                        .log(&format!("Error sending ws frame: {e}"));
+                        .log("Received text message is not valid UTF-8");
+                    close_code = CloseCode::Invalid.into();
+                    close_reason = INVALID_UTF8_CLOSE_REASON;
                     break;
                 }
-                session_data.ws_logger.log_duration(start.elapsed())
+
+                if fragment_mode == WsFragmentMode::Mirror {
+                    let echo_opcode = match frame.opcode {
+                        OpCode::Continuation => OpCode::Continuation,
+                        _ => opcode,
+                    };
+                    let echo_payload = match deflate.as_mut() {
+                        Some(deflate) => Payload::Owned(deflate.compress(&payload, frame.fin)),
+                        None => Payload::Borrowed(&payload),
+                    };
+                    let echo_frame = Frame::new(frame.fin, echo_opcode, None, echo_payload);
+                    if !echo(&mut ws, echo_frame, &session_data).await {
+                        break;
+                    }
+                }
+                if !frame.fin {
+                    continue;
+                }
+
+                let Some(message) = message.take() else {
+                    continue;
+                };
+                session_data
+                    .ws_logger
+                    .log_message(message.opcode, &message.payload);
+                messages += 1;
+                let started = message.started;
+                if let Some(room) = &room {
+                    room.broadcast(message.opcode, message.payload);
+                } else if fragment_mode == WsFragmentMode::Reassemble {
+                    let echo_payload = mode.transform(message.opcode, message.payload);
+                    let echo_frame = outgoing_frame(message.opcode, echo_payload, &mut deflate);
+                    if !echo(&mut ws, echo_frame, &session_data).await {
+                        break;
+                    }
+                }
+                session_data.ws_logger.log_duration(started.elapsed());
             }
             OpCode::Close => {
                 break;
             }
             OpCode::Pong => {
-                got_pong = Some(true);
+                pong_deadline = None;
             }
             _ => {}
         }
     }
 
-    // Try to close connection gracefully if it is still alive
-    if !ws.is_closed() {
-        let close_frame = Frame::close(CloseCode::Normal.into(), &[]);
+    // Try to close connection gracefully if it is still alive and the mode doesn't drop it
+    reader.abort();
+    if !drop_connection && !ws.is_closed() {
+        let close_frame = Frame::close(close_code, close_reason.as_bytes());
         select! {
              _ = ws.write_frame(close_frame) => {},
-             _ = sleep(std::time::Duration::from_secs(1)) => {},
+             _ = sleep(Duration::from_secs(1)) => {},
         };
     }
+    if let Some(room) = room {
+        session_data.ws_logger.log_room_left(room.room());
+    }
     session_data.ws_logger.log_connection_closed();
 }
 
-fn to_response(e: WebSocketError) -> Response<BoxBody<Bytes, BoxedError!()>> {
+/// Read frames until an error and send them to the session
+async fn read_frames<S>(
+    mut reader: WebSocketRead<S>,
+    incoming: mpsc::Sender<Result<Incoming, WebSocketError>>,
+) where
+    S: AsyncRead + Unpin,
+{
+    let replies = incoming.clone();
+    let mut send_reply = |frame: Frame<'static>| {
+        let replies = replies.clone();
+        async move { replies.send(Ok(Incoming::Reply(frame))).await }
+    };
+    loop {
+        let frame = reader.read_frame(&mut send_reply).await.map(|frame| {
+            // Received payloads are never borrowed, so taking them doesn't copy
+            let payload = match frame.payload {
+                Payload::Bytes(payload) => Payload::Bytes(payload),
+                payload => Payload::Owned(payload.to_vec()),
+            };
+            Incoming::Frame(Frame::new(frame.fin, frame.opcode, None, payload))
+        });
+        let failed = frame.is_err();
+        if incoming.send(frame).await.is_err() || failed {
+            break;
+        }
+    }
+}
+
+/// Send an echoed frame applying traffic shaping. Returns `false` if the frame could not be sent.
+async fn echo<S>(
+    ws: &mut WebSocketWrite<WriteHalf<S>>,
+    frame: Frame<'_>,
+    session_data: &SessionData,
+) -> bool
+where
+    S: AsyncWrite,
+{
+    let shaping = &session_data.config.shaping;
+    if shaping.websocket && shaping.is_enabled() {
+        session_data
+            .cancellation_token
+            .run_until_cancelled(sleep(shaping.delay_for(frame.payload.len())))
+            .await;
+    }
+    if let Err(e) = ws.write_frame(frame).await {
+        session_data
+            .ws_logger
+            .log(&format!("Error sending ws frame: {e}"));
+        return false;
+    }
+    true
+}
+
+/// Build a whole message frame compressing the payload if permessage-deflate is negotiated
+fn outgoing_frame(
+    opcode: OpCode,
+    payload: Vec<u8>,
+    deflate: &mut Option<PerMessageDeflate>,
+) -> Frame<'static> {
+    let payload = match deflate.as_mut() {
+        Some(deflate) => deflate.compress(&payload, true),
+        None => payload,
+    };
+    Frame::new(true, opcode, None, Payload::Owned(payload))
+}
+
+/// Wait for the next message of the room or forever if the session is not in a room
+async fn receive(
+    room: &mut Option<RoomMember>,
+    include_own: bool,
+) -> Result<BroadcastMessage, RecvError> {
+    match room {
+        Some(room) => room.receive(include_own).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait until the deadline or forever if there is no deadline
+async fn wait_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next tick of the interval or forever if there is no interval.
+/// Returns the time the tick was scheduled for.
+async fn tick(interval: &mut Option<Interval>) -> tokio::time::Instant {
+    match interval {
+        Some(interval) => interval.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn to_response(e: WebSocketError) -> EchoResponse {
     let body = Full::new(Bytes::from(e.to_string()));
     let response = Response::builder()
         .status(StatusCode::BAD_REQUEST)
@@ -0,0 +1,633 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+};
+
+use fastwebsockets::OpCode;
+use hyper::{HeaderMap, header::SEC_WEBSOCKET_EXTENSIONS};
+use miniz_oxide::{
+    DataFormat, MZError, MZFlush, MZStatus,
+    deflate::{
+        core::{CompressorOxide, create_comp_flags_from_zip_params},
+        stream::deflate,
+    },
+    inflate::stream::{InflateState, inflate},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const EXTENSION_NAME: &str = "permessage-deflate";
+const SERVER_NO_CONTEXT_TAKEOVER: &str = "server_no_context_takeover";
+const CLIENT_NO_CONTEXT_TAKEOVER: &str = "client_no_context_takeover";
+const SERVER_MAX_WINDOW_BITS: &str = "server_max_window_bits";
+const CLIENT_MAX_WINDOW_BITS: &str = "client_max_window_bits";
+
+const MIN_WINDOW_BITS: u8 = 8;
+const MAX_WINDOW_BITS: u8 = 15;
+const COMPRESSION_LEVEL: i32 = 6;
+/// Empty stored block ending every compressed message, omitted on the wire
+const MESSAGE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+const BUFFER_SIZE: usize = 16 * 1024;
+
+const RSV1: u8 = 0x40;
+const OPCODE_MASK: u8 = 0x0f;
+const TEXT_OPCODE: u8 = 0x1;
+const BINARY_OPCODE: u8 = 0x2;
+const MAX_HEADER_LEN: usize = 14;
+
+/// `permessage-deflate` compression settings (RFC 7692) of WebSocket connections.
+///
+/// Compression is used only if a client offers the extension in the `sec-websocket-extensions` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeflateConfig {
+    /// Max window bits (from 8 to 15) used by the server to compress messages.
+    /// A smaller value requested by a client is respected.
+    pub server_max_window_bits: u8,
+    /// Max window bits (from 8 to 15) the client has to use to compress messages.
+    /// Offers of clients not supporting `client_max_window_bits` are declined if set.
+    pub client_max_window_bits: Option<u8>,
+    /// Reset the compression context of the server after every message
+    pub server_no_context_takeover: bool,
+    /// Ask the client to reset its compression context after every message
+    pub client_no_context_takeover: bool,
+}
+
+impl Default for DeflateConfig {
+    fn default() -> Self {
+        Self {
+            server_max_window_bits: MAX_WINDOW_BITS,
+            client_max_window_bits: None,
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+        }
+    }
+}
+
+impl DeflateConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let window_bits = [
+            ("Server", Some(self.server_max_window_bits)),
+            ("Client", self.client_max_window_bits),
+        ];
+        for (name, bits) in window_bits {
+            if let Some(bits) = bits
+                && !(MIN_WINDOW_BITS..=MAX_WINDOW_BITS).contains(&bits)
+            {
+                return Err(format!(
+                    "{name} max window bits must be in range [{MIN_WINDOW_BITS}, {MAX_WINDOW_BITS}], got {bits}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Negotiated `permessage-deflate` parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(in crate::service) struct DeflateParams {
+    server_max_window_bits: Option<u8>,
+    client_max_window_bits: Option<u8>,
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+}
+
+impl DeflateParams {
+    /// Accept the first acceptable `permessage-deflate` offer of the request headers
+    pub fn negotiate(headers: &HeaderMap, config: &DeflateConfig) -> Option<Self> {
+        headers
+            .get_all(SEC_WEBSOCKET_EXTENSIONS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(Offer::parse)
+            .find_map(|offer| Self::accept(&offer, config))
+    }
+
+    fn accept(offer: &Offer, config: &DeflateConfig) -> Option<Self> {
+        let server_bits = offer
+            .server_max_window_bits
+            .unwrap_or(MAX_WINDOW_BITS)
+            .min(config.server_max_window_bits);
+        let client_max_window_bits =
+            match (offer.client_max_window_bits, config.client_max_window_bits) {
+                // The client can't be limited if it doesn't support the parameter
+                (None, Some(_)) => return None,
+                (Some(offered), Some(bits)) => Some(bits.min(offered.unwrap_or(MAX_WINDOW_BITS))),
+                (_, None) => None,
+            };
+
+        Some(Self {
+            server_max_window_bits: (offer.server_max_window_bits.is_some()
+                || server_bits < MAX_WINDOW_BITS)
+                .then_some(server_bits),
+            client_max_window_bits,
+            server_no_context_takeover: offer.server_no_context_takeover
+                || config.server_no_context_takeover,
+            client_no_context_takeover: offer.client_no_context_takeover
+                || config.client_no_context_takeover,
+        })
+    }
+}
+
+/// Formats the parameters as a value of the `sec-websocket-extensions` response header
+impl Display for DeflateParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{EXTENSION_NAME}")?;
+        if self.server_no_context_takeover {
+            write!(f, "; {SERVER_NO_CONTEXT_TAKEOVER}")?;
+        }
+        if self.client_no_context_takeover {
+            write!(f, "; {CLIENT_NO_CONTEXT_TAKEOVER}")?;
+        }
+        if let Some(bits) = self.server_max_window_bits {
+            write!(f, "; {SERVER_MAX_WINDOW_BITS}={bits}")?;
+        }
+        if let Some(bits) = self.client_max_window_bits {
+            write!(f, "; {CLIENT_MAX_WINDOW_BITS}={bits}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `permessage-deflate` offer of a client
+#[derive(Debug, Default)]
+struct Offer {
+    server_max_window_bits: Option<u8>,
+    /// `Some(None)` if the parameter is present without a value
+    client_max_window_bits: Option<Option<u8>>,
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+}
+
+impl Offer {
+    /// Parse an extension offer. Returns `None` for other extensions and invalid offers.
+    fn parse(extension: &str) -> Option<Self> {
+        let mut params = extension.split(';').map(str::trim);
+        if params.next()? != EXTENSION_NAME {
+            return None;
+        }
+
+        let mut offer = Self::default();
+        let mut seen = Vec::new();
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+
+            match (name, value) {
+                (SERVER_NO_CONTEXT_TAKEOVER, None) => offer.server_no_context_takeover = true,
+                (CLIENT_NO_CONTEXT_TAKEOVER, None) => offer.client_no_context_takeover = true,
+                (SERVER_MAX_WINDOW_BITS, Some(value)) => {
+                    offer.server_max_window_bits = Some(parse_window_bits(value)?);
+                }
+                (CLIENT_MAX_WINDOW_BITS, None) => offer.client_max_window_bits = Some(None),
+                (CLIENT_MAX_WINDOW_BITS, Some(value)) => {
+                    offer.client_max_window_bits = Some(Some(parse_window_bits(value)?));
+                }
+                _ => return None,
+            }
+        }
+        Some(offer)
+    }
+}
+
+fn parse_window_bits(value: &str) -> Option<u8> {
+    value
+        .parse()
+        .ok()
+        .filter(|bits| (MIN_WINDOW_BITS..=MAX_WINDOW_BITS).contains(bits))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in crate::service) enum DeflateError {
+    /// Decompressed message is larger than allowed
+    TooLarge,
+    /// Message could not be decompressed
+    Invalid,
+}
+
+/// First frame of a received data message
+#[derive(Debug, Clone, Copy)]
+pub(in crate::service) struct InboundFrame {
+    /// Opcode sent by the client. Compressed text frames are passed to the WebSocket as binary
+    /// to skip UTF-8 validation of the compressed payload.
+    pub opcode: OpCode,
+    pub compressed: bool,
+}
+
+type InboundFrames = Arc<Mutex<VecDeque<InboundFrame>>>;
+
+/// Compression state of a WebSocket connection with negotiated `permessage-deflate`
+pub(in crate::service) struct PerMessageDeflate {
+    params: DeflateParams,
+    inbound_frames: InboundFrames,
+    compressor: Box<CompressorOxide>,
+    decompressor: Box<InflateState>,
+    /// Whether the client ended the deflate stream in the current message
+    stream_ended: bool,
+}
+
+impl PerMessageDeflate {
+    pub fn new(params: DeflateParams) -> Self {
+        let flags = create_comp_flags_from_zip_params(COMPRESSION_LEVEL, -1, 0);
+        Self {
+            params,
+            inbound_frames: InboundFrames::default(),
+            compressor: Box::new(CompressorOxide::new(flags)),
+            decompressor: InflateState::new_boxed(DataFormat::Raw),
+            stream_ended: false,
+        }
+    }
+
+    pub fn params(&self) -> &DeflateParams {
+        &self.params
+    }
+
+    /// Wrap the connection stream to pass compressed frames to the WebSocket
+    pub fn wrap<S>(&self, inner: S) -> DeflateIo<S> {
+        DeflateIo {
+            inner,
+            inbound_frames: self.inbound_frames.clone(),
+            read_tracker: FrameTracker::default(),
+            write_tracker: FrameTracker::default(),
+        }
+    }
+
+    /// Description of the first data frame read by the WebSocket since the last call
+    pub fn next_inbound_frame(&self) -> Option<InboundFrame> {
+        self.inbound_frames.lock().unwrap().pop_front()
+    }
+
+    /// Decompress a fragment of a compressed message.
+    /// - `fin` - whether it is the last fragment of the message
+    /// - `limit` - max size of the decompressed data
+    pub fn decompress(
+        &mut self,
+        data: &[u8],
+        fin: bool,
+        limit: usize,
+    ) -> Result<Vec<u8>, DeflateError> {
+        let mut output = Vec::new();
+        self.inflate(data, &mut output, limit)?;
+        if fin {
+            if !self.stream_ended {
+                self.inflate(&MESSAGE_TAIL, &mut output, limit)?;
+            }
+            if self.params.client_no_context_takeover {
+                self.decompressor.reset(DataFormat::Raw);
+            }
+            self.stream_ended = false;
+        }
+        Ok(output)
+    }
+
+    fn inflate(
+        &mut self,
+        mut input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<(), DeflateError> {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        while !self.stream_ended {
+            let result = inflate(&mut self.decompressor, input, &mut buffer, MZFlush::None);
+            input = &input[result.bytes_consumed..];
+            output.extend_from_slice(&buffer[..result.bytes_written]);
+            if output.len() > limit {
+                return Err(DeflateError::TooLarge);
+            }
+
+            let output_full = result.bytes_written == buffer.len();
+            match result.status {
+                // The client finished the stream with a final block
+                Ok(MZStatus::StreamEnd) => {
+                    self.decompressor.reset(DataFormat::Raw);
+                    self.stream_ended = true;
+                }
+                Ok(_) if input.is_empty() && !output_full => break,
+                Ok(_) => {}
+                Err(MZError::Buf) if input.is_empty() => break,
+                Err(_) => return Err(DeflateError::Invalid),
+            }
+        }
+        Ok(())
+    }
+
+    /// Compress a fragment of a message to send.
+    /// - `fin` - whether it is the last fragment of the message
+    pub fn compress(&mut self, data: &[u8], fin: bool) -> Vec<u8> {
+        let window_bits = self
+            .params
+            .server_max_window_bits
+            .unwrap_or(MAX_WINDOW_BITS);
+        let mut output = Vec::new();
+        if window_bits < MAX_WINDOW_BITS {
+            // The compressor always uses the max window, so the data is compressed in independent
+            // parts no larger than the window instead
+            let window = 1 << window_bits;
+            for part in data.chunks(window) {
+                self.deflate(part, MZFlush::Full, &mut output);
+            }
+            if data.is_empty() {
+                self.deflate(data, MZFlush::Full, &mut output);
+            }
+        } else {
+            let flush = if fin && self.params.server_no_context_takeover {
+                MZFlush::Full
+            } else {
+                MZFlush::Sync
+            };
+            self.deflate(data, flush, &mut output);
+        }
+
+        if fin && output.ends_with(&MESSAGE_TAIL) {
+            output.truncate(output.len() - MESSAGE_TAIL.len());
+        }
+        output
+    }
+
+    fn deflate(&mut self, mut input: &[u8], flush: MZFlush, output: &mut Vec<u8>) {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            let result = deflate(&mut self.compressor, input, &mut buffer, flush);
+            input = &input[result.bytes_consumed..];
+            output.extend_from_slice(&buffer[..result.bytes_written]);
+            if result.status.is_err() || (input.is_empty() && result.bytes_written < buffer.len()) {
+                break;
+            }
+        }
+    }
+}
+
+/// Tracks frame boundaries in a stream of WebSocket frames
+#[derive(Debug, Default, Clone)]
+struct FrameTracker {
+    header: [u8; MAX_HEADER_LEN],
+    header_len: usize,
+    payload_left: u64,
+}
+
+impl FrameTracker {
+    /// Whether the next byte of the stream is the first byte of a frame
+    fn at_frame_start(&self) -> bool {
+        self.header_len == 0 && self.payload_left == 0
+    }
+
+    /// Number of bytes until the end of the current header or payload
+    fn span(&self) -> usize {
+        if self.payload_left > 0 {
+            return usize::try_from(self.payload_left).unwrap_or(usize::MAX);
+        }
+        if self.header_len < 2 {
+            return 2 - self.header_len;
+        }
+        self.header_size() - self.header_len
+    }
+
+    /// Account bytes passed through the stream, no more than [FrameTracker::span]
+    fn advance(&mut self, data: &[u8]) {
+        if self.payload_left > 0 {
+            self.payload_left -= data.len() as u64;
+            return;
+        }
+
+        self.header[self.header_len..self.header_len + data.len()].copy_from_slice(data);
+        self.header_len += data.len();
+        if self.header_len >= 2 && self.header_len == self.header_size() {
+            self.payload_left = self.payload_len();
+            self.header_len = 0;
+        }
+    }
+
+    /// Account any number of bytes calling `on_frame_start` with the offset of every frame start in `data`
+    fn advance_all(&mut self, data: &[u8], mut on_frame_start: impl FnMut(usize)) {
+        let mut position = 0;
+        while position < data.len() {
+            if self.at_frame_start() {
+                on_frame_start(position);
+            }
+            let len = self.span().min(data.len() - position);
+            self.advance(&data[position..position + len]);
+            position += len;
+        }
+    }
+
+    fn header_size(&self) -> usize {
+        let mask_len = if self.header[1] & 0x80 != 0 { 4 } else { 0 };
+        let extended_len = match self.header[1] & 0x7f {
+            126 => 2,
+            127 => 8,
+            _ => 0,
+        };
+        2 + extended_len + mask_len
+    }
+
+    fn payload_len(&self) -> u64 {
+        match self.header[1] & 0x7f {
+            126 => u16::from_be_bytes([self.header[2], self.header[3]]).into(),
+            127 => u64::from_be_bytes(self.header[2..10].try_into().unwrap()),
+            len => len.into(),
+        }
+    }
+}
+
+/// Stream wrapper hiding the `RSV1` bit of compressed frames from the WebSocket
+/// and setting it on sent data frames, which are always compressed
+pub(in crate::service) struct DeflateIo<S> {
+    inner: S,
+    inbound_frames: InboundFrames,
+    read_tracker: FrameTracker,
+    write_tracker: FrameTracker,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateIo<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+
+        let this = &mut *self;
+        let data = &mut buf.filled_mut()[filled..];
+        let mut position = 0;
+        while position < data.len() {
+            if this.read_tracker.at_frame_start() {
+                let first_byte = &mut data[position];
+                let opcode = *first_byte & OPCODE_MASK;
+                if opcode == TEXT_OPCODE || opcode == BINARY_OPCODE {
+                    let compressed = *first_byte & RSV1 != 0;
+                    if compressed {
+                        *first_byte = (*first_byte & !RSV1 & !OPCODE_MASK) | BINARY_OPCODE;
+                    }
+                    this.inbound_frames.lock().unwrap().push_back(InboundFrame {
+                        opcode: if opcode == TEXT_OPCODE {
+                            OpCode::Text
+                        } else {
+                            OpCode::Binary
+                        },
+                        compressed,
+                    });
+                }
+            }
+            let len = this.read_tracker.span().min(data.len() - position);
+            this.read_tracker.advance(&data[position..position + len]);
+            position += len;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateIo<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        // Headers are rewritten in a copy, so they are sent together with the rest of the data.
+        // If only a part is written, the rest is passed again and rewritten the same way.
+        let mut rewritten: Option<Vec<u8>> = None;
+        this.write_tracker.clone().advance_all(buf, |position| {
+            let opcode = buf[position] & OPCODE_MASK;
+            if opcode == TEXT_OPCODE || opcode == BINARY_OPCODE {
+                rewritten.get_or_insert_with(|| buf.to_vec())[position] |= RSV1;
+            }
+        });
+        let data = rewritten.as_deref().unwrap_or(buf);
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, data))?;
+        this.write_tracker.advance_all(&buf[..written], |_| {});
+        Poll::Ready(Ok(written))
+    }
+
+    /// Header and payload of a frame are written by the WebSocket as separate slices,
+    /// they are joined to be rewritten and sent together
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let buf: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        self.poll_write(cx, &buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::IoSlice;
+
+    use hyper::{HeaderMap, header::SEC_WEBSOCKET_EXTENSIONS};
+    use tokio::io::AsyncWriteExt;
+
+    use super::{DeflateConfig, DeflateParams, PerMessageDeflate};
+
+    fn negotiate(offer: &str, config: &DeflateConfig) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(SEC_WEBSOCKET_EXTENSIONS, offer.parse().unwrap());
+        DeflateParams::negotiate(&headers, config).map(|params| params.to_string())
+    }
+
+    #[test]
+    fn offers_are_negotiated() {
+        let config = DeflateConfig::default();
+        assert_eq!(
+            negotiate("permessage-deflate", &config).as_deref(),
+            Some("permessage-deflate")
+        );
+        assert_eq!(
+            negotiate(
+                "permessage-deflate; client_max_window_bits; server_max_window_bits=10",
+                &config
+            )
+            .as_deref(),
+            Some("permessage-deflate; server_max_window_bits=10")
+        );
+        assert_eq!(negotiate("x-webkit-deflate-frame", &config), None);
+        assert_eq!(
+            negotiate("permessage-deflate; server_max_window_bits=20", &config),
+            None
+        );
+
+        let config = DeflateConfig {
+            server_max_window_bits: 12,
+            client_max_window_bits: Some(11),
+            server_no_context_takeover: true,
+            client_no_context_takeover: true,
+        };
+        assert_eq!(
+            negotiate(
+                "permessage-deflate; foo, permessage-deflate; client_max_window_bits",
+                &config
+            )
+            .as_deref(),
+            Some(
+                "permessage-deflate; server_no_context_takeover; client_no_context_takeover; \
+                 server_max_window_bits=12; client_max_window_bits=11"
+            )
+        );
+        assert_eq!(negotiate("permessage-deflate", &config), None);
+    }
+
+    #[test]
+    fn messages_survive_compression() {
+        let messages = [
+            b"".to_vec(),
+            b"Hello, hello, hello".to_vec(),
+            (0..100_000u32).map(|i| (i % 251) as u8).collect(),
+        ];
+        for server_max_window_bits in [None, Some(9)] {
+            let params = DeflateParams {
+                server_max_window_bits,
+                client_max_window_bits: None,
+                server_no_context_takeover: false,
+                client_no_context_takeover: false,
+            };
+            let mut sender = PerMessageDeflate::new(params.clone());
+            let mut receiver = PerMessageDeflate::new(params);
+            for message in &messages {
+                let compressed = sender.compress(message, true);
+                let decompressed = receiver.decompress(&compressed, true, usize::MAX).unwrap();
+                assert_eq!(&decompressed, message);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_are_written_whole() {
+        let deflate = PerMessageDeflate::new(DeflateParams {
+            server_max_window_bits: None,
+            client_max_window_bits: None,
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+        });
+        let mut io = deflate.wrap(Vec::new());
+
+        // Text and close frames in one write, then a binary frame in two slices
+        let data = [0x81, 0x02, b'h', b'i', 0x88, 0x00];
+        assert_eq!(io.write(&data).await.unwrap(), data.len());
+        let header = [0x82, 0x01];
+        let slices = [IoSlice::new(&header), IoSlice::new(&[0xff])];
+        assert_eq!(io.write_vectored(&slices).await.unwrap(), 3);
+
+        assert_eq!(
+            io.inner,
+            [0xc1, 0x02, b'h', b'i', 0x88, 0x00, 0xc2, 0x01, 0xff]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use fastwebsockets::OpCode;
+use hyper::body::Bytes;
+use tokio::sync::broadcast::{self, Receiver, Sender, error::RecvError};
+
+/// Max number of messages kept for room members which are slow to receive them
+const CAPACITY: usize = 1024;
+
+/// Message sent to all the members of a room
+#[derive(Debug, Clone)]
+pub(in crate::service) struct BroadcastMessage {
+    pub sender: u64,
+    pub opcode: OpCode,
+    pub payload: Bytes,
+}
+
+#[derive(Debug)]
+struct Room {
+    sender: Sender<BroadcastMessage>,
+    members: usize,
+}
+
+/// Registry of the broadcast rooms of an [EchoServer](crate::EchoServer).
+/// A room exists while it has members.
+#[derive(Debug, Default)]
+pub(crate) struct WsHub {
+    rooms: Mutex<HashMap<String, Room>>,
+    next_member_id: AtomicU64,
+}
+
+impl WsHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Join the room creating it if needed
+    pub(in crate::service) fn join(self: &Arc<Self>, room: &str) -> RoomMember {
+        let mut rooms = self.rooms.lock().unwrap();
+        let entry = rooms.entry(room.to_string()).or_insert_with(|| Room {
+            sender: broadcast::channel(CAPACITY).0,
+            members: 0,
+        });
+        entry.members += 1;
+        RoomMember {
+            hub: self.clone(),
+            room: room.to_string(),
+            id: self.next_member_id.fetch_add(1, Ordering::Relaxed),
+            members: entry.members,
+            sender: entry.sender.clone(),
+            receiver: entry.sender.subscribe(),
+        }
+    }
+
+    fn leave(&self, room: &str) {
+        let mut rooms = self.rooms.lock().unwrap();
+        if let Some(entry) = rooms.get_mut(room) {
+            entry.members -= 1;
+            if entry.members == 0 {
+                rooms.remove(room);
+            }
+        }
+    }
+}
+
+/// Membership of a session in a room, the session leaves the room when it is dropped
+#[derive(Debug)]
+pub(in crate::service) struct RoomMember {
+    hub: Arc<WsHub>,
+    room: String,
+    id: u64,
+    members: usize,
+    sender: Sender<BroadcastMessage>,
+    receiver: Receiver<BroadcastMessage>,
+}
+
+impl RoomMember {
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    /// Number of members of the room when the session joined it, including the session
+    pub fn members_on_join(&self) -> usize {
+        self.members
+    }
+
+    pub fn broadcast(&self, opcode: OpCode, payload: Vec<u8>) {
+        // Can't fail as the member itself is subscribed
+        let _ = self.sender.send(BroadcastMessage {
+            sender: self.id,
+            opcode,
+            payload: payload.into(),
+        });
+    }
+
+    /// Receive the next message of the room, skipping own messages unless `include_own` is set
+    pub async fn receive(&mut self, include_own: bool) -> Result<BroadcastMessage, RecvError> {
+        loop {
+            let message = self.receiver.recv().await?;
+            if include_own || message.sender != self.id {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+impl Drop for RoomMember {
+    fn drop(&mut self) {
+        self.hub.leave(&self.room);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fastwebsockets::OpCode;
+
+    use super::WsHub;
+
+    #[tokio::test]
+    async fn messages_are_delivered_to_room_members() {
+        let hub = Arc::new(WsHub::new());
+        let mut first = hub.join("chat");
+        let mut second = hub.join("chat");
+        let mut other = hub.join("other");
+        assert_eq!(second.members_on_join(), 2);
+
+        first.broadcast(OpCode::Text, b"hello".to_vec());
+        let message = second.receive(false).await.unwrap();
+        assert_eq!(message.payload.as_ref(), b"hello");
+        let message = first.receive(true).await.unwrap();
+        assert_eq!(message.payload.as_ref(), b"hello");
+        assert!(other.receiver.try_recv().is_err());
+
+        // Own messages are skipped
+        first.broadcast(OpCode::Text, b"own".to_vec());
+        second.broadcast(OpCode::Text, b"reply".to_vec());
+        let message = first.receive(false).await.unwrap();
+        assert_eq!(message.payload.as_ref(), b"reply");
+    }
+
+    #[test]
+    fn empty_rooms_are_removed() {
+        let hub = Arc::new(WsHub::new());
+        let first = hub.join("chat");
+        let second = hub.join("chat");
+        drop(first);
+        assert_eq!(hub.rooms.lock().unwrap()["chat"].members, 1);
+        drop(second);
+        assert!(hub.rooms.lock().unwrap().is_empty());
+        assert_eq!(hub.join("chat").members_on_join(), 1);
+    }
+}
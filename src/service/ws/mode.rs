@@ -0,0 +1,293 @@
+use std::time::Duration;
+
+use fastwebsockets::{CloseCode, OpCode};
+use hyper::Request;
+
+const MODE_PARAM: &str = "mode";
+const INTERVAL_PARAM: &str = "interval";
+const COUNT_PARAM: &str = "count";
+const AFTER_PARAM: &str = "after";
+const CODE_PARAM: &str = "code";
+const REASON_PARAM: &str = "reason";
+const ROOM_PARAM: &str = "room";
+/// Prefix of paths selecting a mode, e.g. `/ws/reverse` or `/ws/broadcast/{room}`
+const MODE_PATH_PREFIX: &str = "/ws/";
+/// Modes which could be selected by the `/ws/{mode}` path
+const MODES: [&str; 7] = [
+    "echo",
+    "broadcast",
+    "uppercase",
+    "reverse",
+    "periodic",
+    "close",
+    "drop",
+];
+/// Room joined in broadcast mode if none is requested
+const DEFAULT_ROOM: &str = "default";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_CLOSE_REASON_LEN: usize = 123;
+
+/// Behaviour of a WebSocket session selected by the `mode` query parameter
+/// or the `/ws/{mode}` path of the upgrade request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(in crate::service) enum WsMode {
+    /// Echo messages back
+    Echo,
+    /// Send messages to all the sessions in the room, to the sender too if `ws_broadcast_to_sender` is set
+    Broadcast { room: String },
+    /// Echo messages in upper case
+    Uppercase,
+    /// Echo messages reversed
+    Reverse,
+    /// Echo messages and send `count` messages (unlimited if `None`) every `interval`
+    Periodic {
+        interval: Duration,
+        count: Option<u64>,
+    },
+    /// Close the session with `code` and `reason` after echoing `after` messages
+    Close {
+        after: u64,
+        code: u16,
+        reason: String,
+    },
+    /// Drop the connection without a close frame after echoing `after` messages
+    Drop { after: u64 },
+}
+
+/// How a session is ended by its mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in crate::service) enum SessionEnd<'a> {
+    Close { code: u16, reason: &'a str },
+    Drop,
+}
+
+impl WsMode {
+    pub fn from_request<B>(request: &Request<B>) -> Result<Self, String> {
+        let query = request.uri().query().unwrap_or_default();
+        let params: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(param, _)| param == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let number = |name: &str| {
+            param(name)
+                .map(|value| {
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| format!("Invalid {name} parameter {value}"))
+                })
+                .transpose()
+        };
+
+        // Other paths are echoed as before, so only known modes are taken from the path
+        let (path_mode, path_room) = match request.uri().path().strip_prefix(MODE_PATH_PREFIX) {
+            Some(path) => match path.split_once('/') {
+                Some((mode, room)) => (Some(mode), Some(room)),
+                None => (Some(path), None),
+            },
+            None => (None, None),
+        };
+        let path_mode = path_mode.filter(|mode| MODES.contains(mode));
+        let Some(mode) = param(MODE_PARAM).or(path_mode) else {
+            return Ok(Self::Echo);
+        };
+        let room = param(ROOM_PARAM)
+            .or(path_room.filter(|_| path_mode == Some("broadcast")))
+            .filter(|room| !room.is_empty());
+
+        match mode {
+            "echo" => Ok(Self::Echo),
+            "broadcast" => Ok(Self::Broadcast {
+                room: room.unwrap_or(DEFAULT_ROOM).to_string(),
+            }),
+            "uppercase" => Ok(Self::Uppercase),
+            "reverse" => Ok(Self::Reverse),
+            "periodic" => {
+                let interval = number(INTERVAL_PARAM)?
+                    .map(Duration::from_millis)
+                    .unwrap_or(DEFAULT_INTERVAL);
+                if interval.is_zero() {
+                    return Err("Interval must be greater than zero".to_string());
+                }
+                Ok(Self::Periodic {
+                    interval,
+                    count: number(COUNT_PARAM)?,
+                })
+            }
+            "close" => {
+                let code = param(CODE_PARAM)
+                    .map(|value| {
+                        value
+                            .parse::<u16>()
+                            .ok()
+                            .filter(|code| CloseCode::from(*code).is_allowed())
+                            .ok_or(format!("Invalid close code {value}"))
+                    })
+                    .transpose()?
+                    .unwrap_or(CloseCode::Normal.into());
+                let reason = param(REASON_PARAM).unwrap_or_default();
+                if reason.len() > MAX_CLOSE_REASON_LEN {
+                    return Err(format!(
+                        "Close reason must not be longer than {MAX_CLOSE_REASON_LEN} bytes"
+                    ));
+                }
+                Ok(Self::Close {
+                    after: number(AFTER_PARAM)?.unwrap_or_default(),
+                    code,
+                    reason: reason.to_string(),
+                })
+            }
+            "drop" => Ok(Self::Drop {
+                after: number(AFTER_PARAM)?.unwrap_or_default(),
+            }),
+            _ => Err(format!("Unknown WebSocket mode {mode}")),
+        }
+    }
+
+    /// Whether the mode works with whole messages, so fragments can't be mirrored
+    pub fn needs_whole_messages(&self) -> bool {
+        matches!(
+            self,
+            Self::Broadcast { .. } | Self::Uppercase | Self::Reverse
+        )
+    }
+
+    /// Returns how to end the session after `messages` were echoed or `None` to continue
+    pub fn session_end(&self, messages: u64) -> Option<SessionEnd<'_>> {
+        match self {
+            Self::Close {
+                after,
+                code,
+                reason,
+            } if messages >= *after => Some(SessionEnd::Close {
+                code: *code,
+                reason,
+            }),
+            Self::Drop { after } if messages >= *after => Some(SessionEnd::Drop),
+            _ => None,
+        }
+    }
+
+    /// Payload to echo for a received message. Text messages are expected to be valid UTF-8.
+    pub fn transform(&self, opcode: OpCode, mut payload: Vec<u8>) -> Vec<u8> {
+        match (self, opcode) {
+            (Self::Uppercase, OpCode::Text) => String::from_utf8_lossy(&payload)
+                .to_uppercase()
+                .into_bytes(),
+            (Self::Reverse, OpCode::Text) => String::from_utf8_lossy(&payload)
+                .chars()
+                .rev()
+                .collect::<String>()
+                .into_bytes(),
+            (Self::Uppercase, _) => {
+                payload.make_ascii_uppercase();
+                payload
+            }
+            (Self::Reverse, _) => {
+                payload.reverse();
+                payload
+            }
+            _ => payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fastwebsockets::OpCode;
+    use hyper::Request;
+
+    use super::{SessionEnd, WsMode};
+
+    fn mode(uri: &str) -> Result<WsMode, String> {
+        WsMode::from_request(&Request::get(uri).body(()).unwrap())
+    }
+
+    #[test]
+    fn modes_are_parsed() {
+        assert_eq!(mode("/"), Ok(WsMode::Echo));
+        assert_eq!(mode("/ws/reverse"), Ok(WsMode::Reverse));
+        assert_eq!(mode("/ws/reverse?mode=uppercase"), Ok(WsMode::Uppercase));
+        assert_eq!(
+            mode("/?mode=periodic&interval=100&count=3"),
+            Ok(WsMode::Periodic {
+                interval: Duration::from_millis(100),
+                count: Some(3)
+            })
+        );
+        assert_eq!(
+            mode("/?mode=close&after=2&code=4000&reason=bye"),
+            Ok(WsMode::Close {
+                after: 2,
+                code: 4000,
+                reason: "bye".to_string()
+            })
+        );
+        assert_eq!(
+            mode("/ws/broadcast"),
+            Ok(WsMode::Broadcast {
+                room: "default".to_string()
+            })
+        );
+        for uri in ["/ws/broadcast/chat", "/ws?mode=broadcast&room=chat"] {
+            assert_eq!(
+                mode(uri),
+                Ok(WsMode::Broadcast {
+                    room: "chat".to_string()
+                })
+            );
+        }
+        assert_eq!(mode("/ws/foo"), Ok(WsMode::Echo));
+        assert_eq!(mode("/ws/foo/bar"), Ok(WsMode::Echo));
+        assert_eq!(mode("/ws/echo/chat"), Ok(WsMode::Echo));
+        assert_eq!(mode("/?room=chat"), Ok(WsMode::Echo));
+        assert_eq!(mode("/ws/reverse?room=chat"), Ok(WsMode::Reverse));
+        assert!(mode("/?mode=close&code=1005").is_err());
+        assert!(mode("/?mode=periodic&interval=0").is_err());
+        assert!(mode("/?mode=unknown").is_err());
+    }
+
+    #[test]
+    fn sessions_end_after_messages() {
+        let close = mode("/?mode=close&after=1&code=4000").unwrap();
+        assert_eq!(close.session_end(0), None);
+        assert_eq!(
+            close.session_end(1),
+            Some(SessionEnd::Close {
+                code: 4000,
+                reason: ""
+            })
+        );
+        assert_eq!(
+            mode("/ws/drop").unwrap().session_end(0),
+            Some(SessionEnd::Drop)
+        );
+        assert_eq!(WsMode::Echo.session_end(100), None);
+    }
+
+    #[test]
+    fn messages_are_transformed() {
+        let transform =
+            |mode: WsMode, opcode, payload: &[u8]| mode.transform(opcode, payload.to_vec());
+        assert_eq!(
+            transform(WsMode::Uppercase, OpCode::Text, "straße".as_bytes()),
+            "STRASSE".as_bytes()
+        );
+        assert_eq!(
+            transform(WsMode::Reverse, OpCode::Text, "abc🙂".as_bytes()),
+            "🙂cba".as_bytes()
+        );
+        assert_eq!(
+            transform(WsMode::Reverse, OpCode::Binary, &[1, 2, 0xff]),
+            [0xff, 2, 1]
+        );
+        assert_eq!(transform(WsMode::Echo, OpCode::Binary, b"abc"), b"abc");
+    }
+}
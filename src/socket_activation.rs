@@ -0,0 +1,195 @@
+use std::{
+    env,
+    io::{Error, ErrorKind},
+    net::TcpListener,
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixListener,
+    },
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use socket2::{Socket, Type};
+
+/// The first file descriptor passed by systemd (`SD_LISTEN_FDS_START`)
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Set once the passed sockets are taken so they are never owned twice
+static LISTEN_FDS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Listening socket inherited from systemd
+#[derive(Debug)]
+pub(crate) enum InheritedListener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Take the listening sockets passed by systemd socket activation (`LISTEN_PID` and `LISTEN_FDS`).
+/// The variables are removed from the environment, so child processes don't take the sockets too.
+///
+/// Fails if no sockets were passed to this process, they were already taken
+/// or a socket is not a listening TCP or Unix stream socket.
+pub(crate) fn take_listeners() -> Result<Vec<InheritedListener>, Error> {
+    let count = listen_fds_count(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    )?;
+    if LISTEN_FDS_TAKEN.swap(true, Ordering::SeqCst) {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "Sockets passed by systemd are already taken",
+        ));
+    }
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        // SAFETY: the server reads these variables only here, once thanks to LISTEN_FDS_TAKEN,
+        // and doesn't access the environment from other threads
+        unsafe { env::remove_var(name) };
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count as RawFd)
+        .map(|fd| {
+            // SAFETY: systemd passes `count` open sockets starting from LISTEN_FDS_START
+            // and LISTEN_FDS_TAKEN guarantees they are owned only here
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            classify(fd)
+        })
+        .collect()
+}
+
+/// Check that the socket is a listening stream socket and wrap it as a TCP or Unix listener.
+/// The socket is closed on exec, as the sockets created by the server.
+fn classify(fd: OwnedFd) -> Result<InheritedListener, Error> {
+    let socket = Socket::from(fd);
+    socket.set_cloexec(true)?;
+    if socket.r#type()? != Type::STREAM {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Socket passed by systemd is not a stream socket, use ListenStream= in the socket unit",
+        ));
+    }
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    if !socket.is_listener()? {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Socket passed by systemd is not listening, Accept=yes is not supported",
+        ));
+    }
+    let address = socket.local_addr()?;
+    if address.is_ipv4() || address.is_ipv6() {
+        Ok(InheritedListener::Tcp(TcpListener::from(socket)))
+    } else if address.is_unix() {
+        Ok(InheritedListener::Unix(UnixListener::from(socket)))
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Socket passed by systemd is neither TCP nor Unix",
+        ))
+    }
+}
+
+/// Number of sockets passed by systemd to the process `pid`
+fn listen_fds_count(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> Result<usize, Error> {
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "No sockets passed by systemd: LISTEN_PID or LISTEN_FDS is not set",
+        ));
+    };
+    if listen_pid.parse::<u32>().ok() != Some(pid) {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Sockets passed by systemd are for another process (LISTEN_PID={listen_pid})"),
+        ));
+    }
+    match listen_fds.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("LISTEN_FDS must be a positive number, got {listen_fds}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::ErrorKind,
+        net::{TcpListener, UdpSocket},
+        os::{fd::OwnedFd, unix::net::UnixListener},
+    };
+
+    use socket2::{Domain, Socket, Type};
+
+    use super::{InheritedListener, classify, listen_fds_count};
+
+    #[test]
+    fn listeners_are_classified() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        match classify(OwnedFd::from(listener)).unwrap() {
+            InheritedListener::Tcp(listener) => assert_eq!(listener.local_addr().unwrap(), address),
+            InheritedListener::Unix(_) => panic!("TCP listener is classified as Unix"),
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("hyper_echo_{}_classify.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        assert!(matches!(
+            classify(OwnedFd::from(listener)).unwrap(),
+            InheritedListener::Unix(_)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn non_listening_sockets_are_rejected() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let e = classify(OwnedFd::from(socket)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        socket
+            .bind(
+                &"127.0.0.1:0"
+                    .parse::<std::net::SocketAddr>()
+                    .unwrap()
+                    .into(),
+            )
+            .unwrap();
+        let e = classify(OwnedFd::from(socket)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn listen_fds_are_counted() {
+        assert_eq!(listen_fds_count(Some("42"), Some("2"), 42).unwrap(), 2);
+    }
+
+    #[test]
+    fn listen_fds_are_required() {
+        let e = listen_fds_count(None, Some("1"), 42).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        let e = listen_fds_count(Some("42"), None, 42).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn listen_fds_of_another_process_are_ignored() {
+        let e = listen_fds_count(Some("43"), Some("1"), 42).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn invalid_listen_fds_are_rejected() {
+        for listen_fds in ["0", "-1", "many"] {
+            let e = listen_fds_count(Some("42"), Some(listen_fds), 42).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+use hyper::{HeaderMap, header::HeaderValue};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        RootCertStore, ServerConfig,
+        crypto::ring::default_provider,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::WebPkiClientVerifier,
+    },
+};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+const ALPN_H2: &[u8] = b"h2";
+const ALPN_HTTP11: &[u8] = b"http/1.1";
+#[cfg(feature = "http3")]
+const ALPN_H3: &[u8] = b"h3";
+
+const CLIENT_CERT_SUBJECT_HEADER: &str = "x-echo-client-cert-subject";
+const CLIENT_CERT_SAN_HEADER: &str = "x-echo-client-cert-san";
+const CLIENT_CERT_FINGERPRINT_HEADER: &str = "x-echo-client-cert-fingerprint";
+
+/// Whether clients must present a certificate (mutual TLS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientCertMode {
+    /// Clients may present a certificate. If presented it must be valid.
+    Optional,
+    /// Clients must present a valid certificate, otherwise the handshake fails.
+    Required,
+}
+
+#[derive(Debug, Clone)]
+struct ClientAuth {
+    ca_certs: Vec<CertificateDer<'static>>,
+    mode: ClientCertMode,
+}
+
+/// TLS settings of an [EchoServer](crate::EchoServer).
+///
+/// When provided to [EchoServerBuilder::tls](crate::EchoServerBuilder::tls) the server accepts
+/// only HTTPS and WSS connections. Both `h2` and `http/1.1` are offered via ALPN.
+///
+/// With [TlsConfig::with_client_auth] the server verifies client certificates and reports
+/// the verified certificate in `x-echo-client-cert-subject`, `x-echo-client-cert-san` and
+/// `x-echo-client-cert-fingerprint` (SHA-256) response headers.
+#[derive(Debug)]
+pub struct TlsConfig {
+    cert_pem: String,
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_auth: Option<ClientAuth>,
+}
+
+impl Clone for TlsConfig {
+    fn clone(&self) -> Self {
+        Self {
+            cert_pem: self.cert_pem.clone(),
+            cert_chain: self.cert_chain.clone(),
+            key: self.key.clone_key(),
+            client_auth: self.client_auth.clone(),
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Create [TlsConfig] from a PEM encoded certificate chain and a PEM encoded private key.
+    ///
+    /// Returns an error if the certificate chain or the key can't be parsed.
+    pub fn from_pem(cert_pem: &[u8], key_pem: &[u8]) -> Result<Self, Error> {
+        let cert_chain = CertificateDer::pem_slice_iter(cert_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid_data(format!("Invalid TLS certificate: {e}")))?;
+        if cert_chain.is_empty() {
+            return Err(invalid_data("No TLS certificate found".to_string()));
+        }
+        let key = PrivateKeyDer::from_pem_slice(key_pem)
+            .map_err(|e| invalid_data(format!("Invalid TLS private key: {e}")))?;
+
+        Ok(Self {
+            cert_pem: String::from_utf8_lossy(cert_pem).into_owned(),
+            cert_chain,
+            key,
+            client_auth: None,
+        })
+    }
+
+    /// Create [TlsConfig] from files containing a PEM encoded certificate chain and a PEM encoded private key.
+    ///
+    /// Returns an error if some file can't be read or parsed.
+    pub fn from_pem_files(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let cert_pem = std::fs::read(cert_path)?;
+        let key_pem = std::fs::read(key_path)?;
+        Self::from_pem(&cert_pem, &key_pem)
+    }
+
+    /// Create [TlsConfig] with a freshly generated self-signed certificate.
+    /// Useful for tests and offline usage.
+    /// - `subject_alt_names` - DNS names or IP addresses the certificate is valid for (e.g. `localhost`)
+    ///
+    /// Use [TlsConfig::certificate_pem] to make clients trust the generated certificate.
+    pub fn self_signed(subject_alt_names: &[&str]) -> Result<Self, Error> {
+        let names: Vec<String> = subject_alt_names.iter().map(|s| s.to_string()).collect();
+        let certified_key = rcgen::generate_simple_self_signed(names)
+            .map_err(|e| Error::other(format!("Failed to generate certificate: {e}")))?;
+        Self::from_pem(
+            certified_key.cert.pem().as_bytes(),
+            certified_key.signing_key.serialize_pem().as_bytes(),
+        )
+    }
+
+    /// Enable mutual TLS: verify client certificates against the provided CA bundle.
+    /// - `ca_pem` - PEM encoded certificates of trusted certificate authorities
+    /// - `mode` - whether a client certificate is required or optional
+    ///
+    /// Returns an error if the CA bundle can't be parsed or is empty.
+    pub fn with_client_auth(mut self, ca_pem: &[u8], mode: ClientCertMode) -> Result<Self, Error> {
+        let ca_certs = CertificateDer::pem_slice_iter(ca_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid_data(format!("Invalid CA certificate: {e}")))?;
+        if ca_certs.is_empty() {
+            return Err(invalid_data("No CA certificate found".to_string()));
+        }
+        self.client_auth = Some(ClientAuth { ca_certs, mode });
+        Ok(self)
+    }
+
+    /// Same as [TlsConfig::with_client_auth] but reads the CA bundle from a file.
+    pub fn with_client_auth_file(
+        self,
+        ca_path: impl AsRef<Path>,
+        mode: ClientCertMode,
+    ) -> Result<Self, Error> {
+        let ca_pem = std::fs::read(ca_path)?;
+        self.with_client_auth(&ca_pem, mode)
+    }
+
+    /// Get PEM encoded certificate chain of the server.
+    pub fn certificate_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    pub(crate) fn make_acceptor(&self) -> Result<TlsAcceptor, Error> {
+        let mut server_config = self.server_config()?;
+        server_config.alpn_protocols = vec![ALPN_H2.to_vec(), ALPN_HTTP11.to_vec()];
+        Ok(TlsAcceptor::from(Arc::new(server_config)))
+    }
+
+    /// Make QUIC config of an HTTP/3 endpoint using the same certificate and client auth
+    #[cfg(feature = "http3")]
+    pub(crate) fn make_quic_config(&self) -> Result<quinn::ServerConfig, Error> {
+        let mut server_config = self.server_config()?;
+        server_config.alpn_protocols = vec![ALPN_H3.to_vec()];
+        let quic_config = quinn::crypto::rustls::QuicServerConfig::try_from(server_config)
+            .map_err(|e| invalid_data(format!("Invalid QUIC TLS config: {e}")))?;
+        Ok(quinn::ServerConfig::with_crypto(Arc::new(quic_config)))
+    }
+
+    fn server_config(&self) -> Result<ServerConfig, Error> {
+        let provider = Arc::new(default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid_data(e.to_string()))?;
+
+        let builder = match &self.client_auth {
+            None => builder.with_no_client_auth(),
+            Some(client_auth) => {
+                let mut roots = RootCertStore::empty();
+                for cert in &client_auth.ca_certs {
+                    roots
+                        .add(cert.clone())
+                        .map_err(|e| invalid_data(format!("Invalid CA certificate: {e}")))?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = match client_auth.mode {
+                    ClientCertMode::Optional => verifier.allow_unauthenticated(),
+                    ClientCertMode::Required => verifier,
+                };
+                let verifier = verifier
+                    .build()
+                    .map_err(|e| invalid_data(format!("Invalid client auth config: {e}")))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+
+        builder
+            .with_single_cert(self.cert_chain.clone(), self.key.clone_key())
+            .map_err(|e| invalid_data(format!("Invalid TLS certificate or key: {e}")))
+    }
+}
+
+/// Details of a verified client certificate
+#[derive(Debug)]
+pub(crate) struct ClientCert {
+    pub subject: String,
+    pub subject_alt_names: Vec<String>,
+    pub fingerprint: String,
+}
+
+impl ClientCert {
+    pub fn from_der(der: &CertificateDer<'_>) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let subject_alt_names = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|san| san.value.general_names.iter().map(format_name).collect())
+            .unwrap_or_default();
+        let fingerprint = ring::digest::digest(&ring::digest::SHA256, der)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            subject_alt_names,
+            fingerprint,
+        })
+    }
+
+    pub fn add_headers(&self, headers: &mut HeaderMap) {
+        let values = [
+            (CLIENT_CERT_SUBJECT_HEADER, self.subject.clone()),
+            (CLIENT_CERT_SAN_HEADER, self.subject_alt_names.join(", ")),
+            (CLIENT_CERT_FINGERPRINT_HEADER, self.fingerprint.clone()),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+fn format_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(dns) => format!("DNS:{dns}"),
+        GeneralName::IPAddress(ip) => match ip.len() {
+            4 => format!(
+                "IP:{}",
+                std::net::Ipv4Addr::from(<[u8; 4]>::try_from(*ip).unwrap())
+            ),
+            16 => format!(
+                "IP:{}",
+                std::net::Ipv6Addr::from(<[u8; 16]>::try_from(*ip).unwrap())
+            ),
+            _ => format!("IP:{ip:?}"),
+        },
+        GeneralName::RFC822Name(email) => format!("email:{email}"),
+        GeneralName::URI(uri) => format!("URI:{uri}"),
+        other => other.to_string(),
+    }
+}
+
+pub(crate) fn is_h2_negotiated(alpn_protocol: Option<&[u8]>) -> bool {
+    alpn_protocol == Some(ALPN_H2)
+}
+
+fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, ready},
+};
+
+use tokio::net::{UnixListener, UnixStream};
+use tracing::warn;
+
+use crate::connection_info::PeerCredentials;
+
+/// Unix domain socket listener removing its socket file when dropped if the file was created by it
+#[derive(Debug)]
+pub(crate) struct UnixSocketListener {
+    listener: UnixListener,
+    path: Option<PathBuf>,
+    owns_file: bool,
+}
+
+impl UnixSocketListener {
+    /// Listen on `path` replacing a stale socket file left by a stopped server.
+    /// - `mode` - permissions of the socket file, e.g. `0o660`
+    pub async fn bind(path: &Path, mode: Option<u32>) -> Result<Self, Error> {
+        remove_stale_socket(path).await?;
+        let listener = match mode {
+            Some(mode) => bind_with_mode(path, mode)?,
+            None => UnixListener::bind(path)?,
+        };
+        Ok(Self {
+            listener,
+            path: Some(path.to_path_buf()),
+            owns_file: true,
+        })
+    }
+
+    /// Listen on an already bound socket. Its file is left in place when the listener is dropped.
+    pub fn from_std(listener: std::os::unix::net::UnixListener) -> Result<Self, Error> {
+        listener.set_nonblocking(true)?;
+        let path = listener.local_addr()?.as_pathname().map(Path::to_path_buf);
+        Ok(Self {
+            listener: UnixListener::from_std(listener)?,
+            path,
+            owns_file: false,
+        })
+    }
+
+    /// Path of the socket file or `None` for an unnamed or abstract socket
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Accept a connection along with the credentials of the peer process.
+    /// Connections with unknown credentials are dropped.
+    pub fn poll_accept(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(UnixStream, PeerCredentials), Error>> {
+        loop {
+            let (stream, _) = ready!(self.listener.poll_accept(cx))?;
+            match stream.peer_cred() {
+                Ok(credentials) => {
+                    let credentials = PeerCredentials {
+                        pid: credentials.pid(),
+                        uid: credentials.uid(),
+                        gid: credentials.gid(),
+                    };
+                    return Poll::Ready(Ok((stream, credentials)));
+                }
+                Err(e) => warn!("Failed to get credentials of a Unix socket peer: {e}"),
+            }
+        }
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        if let (true, Some(path)) = (self.owns_file, &self.path) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Bind the socket in a private directory next to `path` and move it to `path` once it has `mode`,
+/// so no one could connect while the socket has the permissions given by umask
+fn bind_with_mode(path: &Path, mode: u32) -> Result<UnixListener, Error> {
+    static TEMP_DIRS: AtomicUsize = AtomicUsize::new(0);
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let temp_dir = parent.join(format!(
+        ".hyper_echo-{}-{}",
+        std::process::id(),
+        TEMP_DIRS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&temp_dir)?;
+    let temp_path = temp_dir.join("socket");
+    let listener = UnixListener::bind(&temp_path).and_then(|listener| {
+        fs::set_permissions(&temp_path, fs::Permissions::from_mode(mode))?;
+        fs::rename(&temp_path, path)?;
+        Ok(listener)
+    });
+    // The socket is already moved unless something failed
+    let _ = fs::remove_file(&temp_path);
+    let _ = fs::remove_dir(&temp_dir);
+    listener
+}
+
+/// Remove the socket file at `path` if no one listens on it.
+/// Fails if the path is taken by another file or a running server.
+async fn remove_stale_socket(path: &Path) -> Result<(), Error> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    match UnixStream::connect(path).await {
+        Ok(_) => Err(Error::new(
+            ErrorKind::AddrInUse,
+            format!("{} is used by another server", path.display()),
+        )),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(e) => Err(e),
+    }
+}
@@ -1,6 +1,8 @@
-use std::net::IpAddr;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use fastwebsockets::OpCode;
+use tracing::{Span, field, info, span};
 
-use tracing::{Span, info, span};
+use crate::connection_info::ConnectionInfo;
 
 #[derive(Debug, Clone)]
 pub struct WsLogger {
@@ -8,18 +10,31 @@ pub struct WsLogger {
 }
 
 impl WsLogger {
-    pub fn new(ws_logging_enabled: bool, client_ip: IpAddr, id: u64) -> Self {
+    pub fn new(ws_logging_enabled: bool, connection_info: &ConnectionInfo) -> Self {
         if !ws_logging_enabled {
             Self { span: None }
         } else {
-            Self {
-                span: Some(span!(
-                    tracing::Level::INFO,
-                    "ws client",
-                    ip = ?client_ip,
-                    id = id
-                )),
-            }
+            let span = span!(
+                tracing::Level::INFO,
+                "ws client",
+                ip = connection_info.client_addr.ip().map(field::debug),
+                pid = connection_info.client_addr.pid(),
+                uid = connection_info.client_addr.uid(),
+                id = connection_info.id,
+                client_cert = field::Empty,
+                client_cert_san = field::Empty,
+                client_cert_fingerprint = field::Empty,
+                subprotocol = field::Empty
+            );
+            connection_info.record_span_fields(&span);
+            Self { span: Some(span) }
+        }
+    }
+
+    /// Record the subprotocol selected for the connection in the log span
+    pub fn record_subprotocol(&self, protocol: &str) {
+        if let Some(span) = &self.span {
+            span.record("subprotocol", protocol);
         }
     }
 
@@ -32,10 +47,38 @@ impl WsLogger {
         info!("WS: {s}");
     }
 
+    /// Log a received message: text as is, binary as base64
+    pub fn log_message(&self, opcode: OpCode, payload: &[u8]) {
+        if self.span.is_none() {
+            return;
+        }
+
+        match (opcode, std::str::from_utf8(payload)) {
+            (OpCode::Text, Ok(text)) => self.log(text),
+            _ => self.log(&format!(
+                "binary message of {} bytes (base64): {}",
+                payload.len(),
+                STANDARD.encode(payload)
+            )),
+        }
+    }
+
     pub fn log_connection_established(&self) {
         self.log("connection established");
     }
 
+    pub fn log_extensions(&self, extensions: &str) {
+        self.log(&format!("negotiated extensions: {extensions}"));
+    }
+
+    pub fn log_room_joined(&self, room: &str, members: usize) {
+        self.log(&format!("joined room {room} ({members} members)"));
+    }
+
+    pub fn log_room_left(&self, room: &str) {
+        self.log(&format!("left room {room}"));
+    }
+
     pub fn log_connection_closed(&self) {
         self.log("connection closed");
     }
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use hyper_echo::EchoServer;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+async fn get(port: u16, path: &str) -> reqwest::Response {
+    reqwest::get(format!("http://localhost:{port}{path}"))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn health_and_readiness() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+
+    let response = get(port, "/__echo/healthz").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "ok");
+
+    let response = get(port, "/__echo/readyz").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = get(port, "/__echo/unknown").await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn readiness_fails_during_drain() {
+    let echo_server = EchoServer::builder()
+        .shutdown_drain_period(Duration::from_millis(500))
+        .build()
+        .await
+        .unwrap();
+    let port = echo_server.local_addr().port();
+    let cancellation_token = CancellationToken::new();
+    let server = tokio::spawn(echo_server.run(cancellation_token.clone()));
+
+    cancellation_token.cancel();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let response = get(port, "/__echo/readyz").await;
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    let response = get(port, "/__echo/healthz").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    tokio::time::timeout(Duration::from_secs(2), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn server_info() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+
+    let response = get(port, "/__echo/info").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let info: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["uptime_seconds"].is_number());
+    assert!(info["features"].is_array());
+    assert_eq!(info["config"]["admin_prefix"], "/__echo");
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn custom_and_disabled_admin_prefix() {
+    let cancellation_token = CancellationToken::new();
+    let echo_server = EchoServer::builder()
+        .admin_prefix(Some("/admin".to_string()))
+        .build()
+        .await
+        .unwrap();
+    let port = echo_server.local_addr().port();
+    tokio::spawn(echo_server.run(cancellation_token.clone()));
+    assert_eq!(
+        get(port, "/admin/healthz").await.text().await.unwrap(),
+        "ok"
+    );
+
+    let echo_server = EchoServer::builder()
+        .admin_prefix(None)
+        .build()
+        .await
+        .unwrap();
+    let port = echo_server.local_addr().port();
+    tokio::spawn(echo_server.run(cancellation_token.clone()));
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/__echo/healthz"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "some body");
+
+    let result = EchoServer::builder()
+        .admin_prefix(Some("no_slash".to_string()))
+        .build()
+        .await;
+    assert!(result.is_err());
+
+    cancellation_token.cancel();
+}
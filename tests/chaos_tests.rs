@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use hyper_echo::{ChaosConfig, EchoServer};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+async fn send_with_fault(
+    port: u16,
+    fault: &str,
+    after_bytes: usize,
+) -> Result<reqwest::Response, reqwest::Error> {
+    reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .header("x-echo-fault", fault)
+        .header("x-echo-fault-after-bytes", after_bytes.to_string())
+        .body("some body")
+        .send()
+        .await
+}
+
+#[tokio::test]
+async fn close_before_headers() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = send_with_fault(port, "close", 0).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn reset_mid_body() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = send_with_fault(port, "reset", 4).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.is_err());
+}
+
+#[tokio::test]
+async fn truncate_body() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = send_with_fault(port, "truncate", 4).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "some");
+}
+
+#[tokio::test]
+async fn malformed_chunk_mid_body() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let mut stream = TcpStream::connect(("localhost", port)).await.unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nhost: localhost\r\nx-echo-fault: malformed_chunk\r\n\
+              x-echo-fault-after-bytes: 4\r\ncontent-length: 9\r\n\r\nsome body",
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response).to_lowercase();
+
+    assert!(response.starts_with("http/1.1 200 ok\r\n"));
+    assert!(response.contains("transfer-encoding: chunked\r\n"));
+    assert!(response.ends_with("\r\n\r\n4\r\nsome\r\nzz\r\nmalformed chunk\r\n"));
+
+    let response = send_with_fault(port, "malformed_chunk", 4).await.unwrap();
+    assert!(response.text().await.is_err());
+}
+
+#[tokio::test]
+async fn stall_body_until_shutdown() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+
+    let response = send_with_fault(port, "stall", 4).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let text = tokio::time::timeout(Duration::from_millis(100), response.text()).await;
+    assert!(text.is_err());
+
+    let response = send_with_fault(port, "stall", 0).await.unwrap();
+    cancellation_token.cancel();
+    assert!(response.text().await.is_err());
+}
+
+#[tokio::test]
+async fn random_fault_with_probability() {
+    let chaos = ChaosConfig {
+        close_probability: 1.0,
+        ..Default::default()
+    };
+    let port =
+        common::spawn_server_with(CancellationToken::new(), EchoServer::builder().chaos(chaos))
+            .await;
+
+    let response = reqwest::get(format!("http://localhost:{port}/")).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn service_endpoints_are_not_faulted() {
+    let chaos = ChaosConfig {
+        close_probability: 1.0,
+        ..Default::default()
+    };
+    let port = common::spawn_server_with(
+        CancellationToken::new(),
+        EchoServer::builder().chaos(chaos).metrics(true),
+    )
+    .await;
+
+    for path in ["/__echo/healthz", "/__echo/readyz", "/metrics"] {
+        let response = reqwest::get(format!("http://localhost:{port}{path}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    let mut ws_client = common::WsClient::connect(port).await;
+    ws_client.send_message("Some message").await.unwrap();
+    let (_, response) = ws_client.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("Some message"));
+}
+
+#[tokio::test]
+async fn invalid_probability_is_rejected() {
+    let chaos = ChaosConfig {
+        stall_probability: -0.5,
+        ..Default::default()
+    };
+    let result = EchoServer::builder().chaos(chaos).build().await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn invalid_fault_headers_are_rejected() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .header("x-echo-fault", "bogus")
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert!(response.text().await.unwrap().contains("bogus"));
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .header("x-echo-fault", "truncate")
+        .header("x-echo-fault-after-bytes", "many")
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert!(response.text().await.unwrap().contains("many"));
+}
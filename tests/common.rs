@@ -1,7 +1,10 @@
-#[allow(dead_code)]
-use std::time::Duration;
+#![allow(dead_code)]
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
-use fastwebsockets::{FragmentCollector, Frame, OpCode, Payload, WebSocketError};
+use fastwebsockets::{FragmentCollector, Frame, OpCode, Payload, WebSocket, WebSocketError};
 use http_body_util::Empty;
 use hyper::{
     Request,
@@ -9,7 +12,7 @@ use hyper::{
     header::{CONNECTION, UPGRADE},
     upgrade::Upgraded,
 };
-use hyper_echo::{EchoServer, HttpLogLevel};
+use hyper_echo::{EchoServer, EchoServerBuilder, HttpLogLevel};
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use tokio::net::TcpStream;
 use tokio_util::sync::CancellationToken;
@@ -39,16 +42,65 @@ pub async fn spawn_server_with_log_level(
     spawn_server_impl(cancellation_token, None, http_log_level, ws_logging_enabled).await
 }
 
+pub async fn spawn_server_on(
+    cancellation_token: CancellationToken,
+    addrs: &[IpAddr],
+) -> Vec<SocketAddr> {
+    let echo_server = addrs
+        .iter()
+        .fold(EchoServer::builder(), |builder, ip| builder.bind_addr(*ip))
+        .build()
+        .await
+        .unwrap();
+    let local_addrs = echo_server.local_addrs();
+    tokio::spawn(async move {
+        echo_server.run(cancellation_token).await.unwrap();
+    });
+    local_addrs
+}
+
+pub async fn spawn_server_with(
+    cancellation_token: CancellationToken,
+    builder: EchoServerBuilder,
+) -> u16 {
+    let echo_server = builder.build().await.unwrap();
+    let port = echo_server.local_addr().port();
+    tokio::spawn(async move {
+        echo_server.run(cancellation_token).await.unwrap();
+    });
+    port
+}
+
+#[cfg(feature = "tls")]
+pub async fn spawn_tls_server(
+    cancellation_token: CancellationToken,
+    tls_config: hyper_echo::TlsConfig,
+) -> u16 {
+    let echo_server = EchoServer::builder()
+        .tls(Some(tls_config))
+        .build()
+        .await
+        .unwrap();
+    let port = echo_server.local_addr().port();
+    tokio::spawn(async move {
+        echo_server.run(cancellation_token).await.unwrap();
+    });
+    port
+}
+
 async fn spawn_server_impl(
     cancellation_token: CancellationToken,
     ws_ping_interval: Option<Duration>,
     http_log_level: HttpLogLevel,
     ws_logging_enabled: bool,
 ) -> u16 {
-    let mut echo_server = EchoServer::new(None, http_log_level, ws_logging_enabled)
+    let echo_server = EchoServer::builder()
+        .http_log_level(http_log_level)
+        .ws_logging(ws_logging_enabled)
+        .ws_ping_interval(ws_ping_interval)
+        .build()
         .await
         .unwrap();
-    echo_server.set_ws_ping_interval(ws_ping_interval);
     let port = echo_server.local_addr().port();
     tokio::spawn({
         async move {
@@ -58,33 +110,138 @@ async fn spawn_server_impl(
     port
 }
 
+async fn ws_handshake<S>(stream: S, host: &str, path: &str) -> WebSocket<TokioIo<Upgraded>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+{
+    let req = Request::builder()
+        .method("GET")
+        .uri(format!("http://{host}{path}"))
+        .header("Host", host)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "upgrade")
+        .header(
+            "Sec-WebSocket-Key",
+            fastwebsockets::handshake::generate_key(),
+        )
+        .header("Sec-WebSocket-Version", "13")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+
+    let (mut ws, _) = fastwebsockets::handshake::client(&TokioExecutor::new(), req, stream)
+        .await
+        .unwrap();
+    ws.set_auto_pong(false);
+    ws
+}
+
+/// Connect and complete the WebSocket handshake, returning the stream to write raw frames to
+pub async fn connect_ws_stream(port: u16, path: &str) -> TokioIo<Upgraded> {
+    let host = format!("localhost:{port}");
+    let stream = TcpStream::connect(&host).await.unwrap();
+    ws_handshake(stream, &host, path).await.into_inner()
+}
+
+/// Serialize a masked client frame. The masking key is zero, so the payload is sent as is.
+pub fn client_frame_bytes(opcode: OpCode, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode as u8, 0x80 | 127];
+    frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    frame.extend_from_slice(&[0; 4]);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// WebSocket client working with single frames, without reassembling fragmented messages
+pub struct RawWsClient {
+    ws: WebSocket<TokioIo<Upgraded>>,
+}
+
+impl RawWsClient {
+    pub async fn connect(port: u16, path: &str) -> Self {
+        let host = format!("localhost:{port}");
+        let stream = TcpStream::connect(&host).await.unwrap();
+        Self {
+            ws: ws_handshake(stream, &host, path).await,
+        }
+    }
+
+    pub async fn send_frame(
+        &mut self,
+        fin: bool,
+        opcode: OpCode,
+        data: &[u8],
+    ) -> Result<(), WebSocketError> {
+        let frame = Frame::new(fin, opcode, None, Payload::Borrowed(data));
+        self.ws.write_frame(frame).await
+    }
+
+    /// Receive a frame. Returns fin flag, opcode and payload.
+    pub async fn receive_frame(&mut self) -> Result<(bool, OpCode, Vec<u8>), WebSocketError> {
+        let frame = self.ws.read_frame().await?;
+        Ok((frame.fin, frame.opcode, frame.payload.to_vec()))
+    }
+}
+
 pub struct WsClient {
     ws: FragmentCollector<TokioIo<Upgraded>>,
 }
 
 impl WsClient {
     pub async fn connect(port: u16) -> Self {
+        Self::connect_to(port, "/").await
+    }
+
+    pub async fn connect_to(port: u16, path: &str) -> Self {
         let host = format!("localhost:{port}");
         let stream = TcpStream::connect(&host).await.unwrap();
+        let ws = ws_handshake(stream, &host, path).await;
+        Self {
+            ws: FragmentCollector::new(ws),
+        }
+    }
 
-        let req = Request::builder()
-            .method("GET")
-            .uri(format!("http://{}/", &host))
-            .header("Host", host)
-            .header(UPGRADE, "websocket")
-            .header(CONNECTION, "upgrade")
-            .header(
-                "Sec-WebSocket-Key",
-                fastwebsockets::handshake::generate_key(),
-            )
-            .header("Sec-WebSocket-Version", "13")
-            .body(Empty::<Bytes>::new())
-            .unwrap();
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(port: u16, cert_pem: &str) -> Self {
+        use std::sync::Arc;
+        use tokio_rustls::{
+            TlsConnector,
+            rustls::{
+                ClientConfig, RootCertStore,
+                crypto::ring::default_provider,
+                pki_types::{CertificateDer, ServerName, pem::PemObject},
+            },
+        };
 
-        let (mut ws, _) = fastwebsockets::handshake::client(&TokioExecutor::new(), req, stream)
+        let mut root_store = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(cert_pem.as_bytes()) {
+            root_store.add(cert.unwrap()).unwrap();
+        }
+        let client_config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+
+        let host = format!("localhost:{port}");
+        let stream = TcpStream::connect(&host).await.unwrap();
+        let stream = TlsConnector::from(Arc::new(client_config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
             .await
             .unwrap();
-        ws.set_auto_pong(false);
+        Self::handshake(stream, &host).await
+    }
+
+    #[cfg(unix)]
+    pub async fn connect_unix(path: &std::path::Path) -> Self {
+        let stream = tokio::net::UnixStream::connect(path).await.unwrap();
+        Self::handshake(stream, "localhost").await
+    }
+
+    async fn handshake<S>(stream: S, host: &str) -> Self
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+    {
+        let ws = ws_handshake(stream, host, "/").await;
         Self {
             ws: FragmentCollector::new(ws),
         }
@@ -95,6 +252,16 @@ impl WsClient {
         self.ws.write_frame(frame).await
     }
 
+    pub async fn send_frame(&mut self, opcode: OpCode, data: &[u8]) -> Result<(), WebSocketError> {
+        let frame = Frame::new(true, opcode, None, Payload::Borrowed(data));
+        self.ws.write_frame(frame).await
+    }
+
+    pub async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        let frame = Frame::new(true, OpCode::Ping, None, Payload::Borrowed(&[]));
+        self.ws.write_frame(frame).await
+    }
+
     pub async fn send_pong(&mut self) -> Result<(), WebSocketError> {
         let frame = Frame::pong(Payload::Borrowed(&[]));
         self.ws.write_frame(frame).await
@@ -111,4 +278,14 @@ impl WsClient {
 
         Ok((frame.opcode, payload))
     }
+
+    /// Receive a frame skipping pings. Returns opcode and raw payload.
+    pub async fn receive_raw(&mut self) -> Result<(OpCode, Vec<u8>), WebSocketError> {
+        loop {
+            let frame = self.ws.read_frame().await?;
+            if frame.opcode != OpCode::Ping {
+                return Ok((frame.opcode, frame.payload.to_vec()));
+            }
+        }
+    }
 }
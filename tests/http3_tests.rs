@@ -0,0 +1,110 @@
+#![cfg(feature = "http3")]
+
+use std::{net::SocketAddr, sync::Arc};
+
+use hyper::{Request, body::Buf};
+use hyper_echo::{EchoServer, TlsConfig};
+use quinn::{Endpoint, crypto::rustls::QuicClientConfig};
+use tokio_rustls::rustls::{
+    ClientConfig, RootCertStore,
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, pem::PemObject},
+};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+async fn spawn_http3_server(tls_config: &TlsConfig) -> u16 {
+    common::spawn_server_with(
+        CancellationToken::new(),
+        EchoServer::builder()
+            .tls(Some(tls_config.clone()))
+            .http3(true),
+    )
+    .await
+}
+
+fn make_quic_client(tls_config: &TlsConfig) -> Endpoint {
+    let mut root_store = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(tls_config.certificate_pem().as_bytes()) {
+        root_store.add(cert.unwrap()).unwrap();
+    }
+    let mut client_config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_protocol_versions(&[&tokio_rustls::rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    client_config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+        QuicClientConfig::try_from(client_config).unwrap(),
+    )));
+    endpoint
+}
+
+#[tokio::test]
+async fn http3_echo() {
+    let tls_config = TlsConfig::self_signed(&["localhost"]).unwrap();
+    let port = spawn_http3_server(&tls_config).await;
+
+    let endpoint = make_quic_client(&tls_config);
+    let server_addr: SocketAddr = ([127, 0, 0, 1], port).into();
+    let connection = endpoint
+        .connect(server_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection))
+        .await
+        .unwrap();
+    tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+    let request = Request::post(format!("https://localhost:{port}/"))
+        .body(())
+        .unwrap();
+    let mut stream = send_request.send_request(request).await.unwrap();
+    stream
+        .send_data(hyper::body::Bytes::from_static(b"some body"))
+        .await
+        .unwrap();
+    stream.finish().await.unwrap();
+
+    let response = stream.recv_response().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    assert_eq!(body, b"some body");
+}
+
+#[tokio::test]
+async fn alt_svc_is_advertised() {
+    let tls_config = TlsConfig::self_signed(&["localhost"]).unwrap();
+    let port = spawn_http3_server(&tls_config).await;
+    let cert = reqwest::Certificate::from_pem(tls_config.certificate_pem().as_bytes()).unwrap();
+    let client = reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(cert)
+        .build()
+        .unwrap();
+
+    let response = client
+        .get(format!("https://localhost:{port}/"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["alt-svc"],
+        format!("h3=\":{port}\"; ma=86400").as_str()
+    );
+}
+
+#[tokio::test]
+async fn http3_requires_tls() {
+    assert!(EchoServer::builder().http3(true).build().await.is_err());
+}
@@ -1,4 +1,4 @@
-use hyper::header::{ACCEPT, HeaderValue};
+use hyper::header::{ACCEPT, CONTENT_TYPE, HeaderValue};
 use hyper_echo::HttpLogLevel;
 use tokio_util::sync::CancellationToken;
 use tracing_test::traced_test;
@@ -28,6 +28,236 @@ async fn http_echo() {
     assert_eq!(response.text().await.unwrap(), "some body");
 }
 
+#[tokio::test]
+async fn http_inspect() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "http://127.0.0.1:{port}/inspect/path?a=1&b=some%20value&a=2"
+        ))
+        .header("x-repeated", "first")
+        .header("x-repeated", "second")
+        .body(vec![0xff_u8, 0x00, 0x01])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+    let document: serde_json::Value =
+        serde_json::from_str(&response.text().await.unwrap()).unwrap();
+
+    assert_eq!(document["method"], "POST");
+    assert_eq!(document["path"], "/inspect/path");
+    assert_eq!(document["uri"], "/inspect/path?a=1&b=some%20value&a=2");
+    assert_eq!(document["query"]["a"], serde_json::json!(["1", "2"]));
+    assert_eq!(document["query"]["b"], serde_json::json!(["some value"]));
+    assert_eq!(document["version"], "HTTP/1.1");
+    assert_eq!(
+        document["headers"]["x-repeated"],
+        serde_json::json!(["first", "second"])
+    );
+    assert_eq!(document["client"]["ip"], "127.0.0.1");
+    assert!(document["client"]["port"].as_u64().unwrap() > 0);
+    assert_eq!(document["connection_id"], 0);
+    assert_eq!(document["body"], "/wAB");
+    assert_eq!(document["body_encoding"], "base64");
+}
+
+#[tokio::test]
+async fn http_response_control_by_headers() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let start = std::time::Instant::now();
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .header(ACCEPT, "some value")
+        .header("x-echo-status", "503")
+        .header("x-echo-delay-ms", "100")
+        .header("x-echo-set-header", "x-custom: custom value")
+        .header("x-echo-remove-header", "accept")
+        .header("x-echo-body", "overridden body")
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+
+    assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    assert_eq!(response.status(), 503);
+    let headers = response.headers();
+    assert_eq!(headers.get("x-custom").unwrap(), "custom value");
+    assert!(headers.get(ACCEPT).is_none());
+    assert!(
+        headers
+            .keys()
+            .all(|name| !name.as_str().starts_with("x-echo-"))
+    );
+    assert_eq!(response.text().await.unwrap(), "overridden body");
+}
+
+#[tokio::test]
+async fn http_response_control_by_query() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "http://localhost:{port}/?echo_status=201&echo_set_header=x-custom:1"
+        ))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers().get("x-custom").unwrap(), "1");
+    assert_eq!(response.text().await.unwrap(), "some body");
+}
+
+#[tokio::test]
+async fn http_unprefixed_query_is_echoed() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/?status=active&delay=soon"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "some body");
+}
+
+#[tokio::test]
+async fn http_delay_is_limited() {
+    let port = common::spawn_server_with(
+        CancellationToken::new(),
+        hyper_echo::EchoServer::builder().max_response_delay(std::time::Duration::from_millis(100)),
+    )
+    .await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{port}/"))
+        .header("x-echo-delay-ms", "101")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn http_delay_ends_on_shutdown() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+
+    let request = tokio::spawn(
+        reqwest::Client::new()
+            .get(format!("http://localhost:{port}/?echo_delay=60000"))
+            .send(),
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    cancellation_token.cancel();
+
+    let response = tokio::time::timeout(std::time::Duration::from_secs(5), request)
+        .await
+        .expect("delay must end on shutdown")
+        .unwrap();
+    assert!(response.is_ok_and(|response| response.status() == 200));
+}
+
+#[tokio::test]
+async fn http_invalid_response_control() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{port}/"))
+        .header("x-echo-status", "1000")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn http_echo_on_multiple_addresses() {
+    let addrs = ["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+    let local_addrs = common::spawn_server_on(CancellationToken::new(), &addrs).await;
+    assert_eq!(local_addrs.len(), 2);
+    assert!(local_addrs[0].is_ipv4());
+    assert!(local_addrs[1].is_ipv6());
+
+    for addr in local_addrs {
+        let response = reqwest::Client::new()
+            .post(format!("http://{addr}/"))
+            .body("some body")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "some body");
+    }
+}
+
+#[tokio::test]
+async fn http_echo_on_ipv4_and_ipv6_any_addresses() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let echo_server = hyper_echo::EchoServer::builder()
+        .bind_addr("0.0.0.0".parse().unwrap())
+        .bind_addr("::".parse().unwrap())
+        .port(port)
+        .build()
+        .await
+        .unwrap();
+    let local_addrs = echo_server.local_addrs();
+    tokio::spawn(echo_server.run(CancellationToken::new()));
+    assert!(local_addrs.iter().all(|addr| addr.port() == port));
+
+    for host in ["127.0.0.1", "[::1]"] {
+        let response = reqwest::Client::new()
+            .post(format!("http://{host}:{port}/"))
+            .body("some body")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "some body");
+    }
+}
+
+#[tokio::test]
+async fn random_port_is_shared_by_all_addresses() {
+    let addrs = ["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+    let local_addrs = common::spawn_server_on(CancellationToken::new(), &addrs).await;
+    assert_eq!(local_addrs[0].port(), local_addrs[1].port());
+}
+
+#[tokio::test]
+async fn server_with_invalid_config_fails() {
+    let result = hyper_echo::EchoServer::builder()
+        .ws_ping_interval(Some(std::time::Duration::ZERO))
+        .build()
+        .await;
+    assert!(result.is_err());
+
+    let result = hyper_echo::EchoServer::builder()
+        .ws_max_message_size(0)
+        .build()
+        .await;
+    assert!(result.is_err());
+}
+
 #[tokio::test]
 async fn http_request_fails_after_cancel() {
     let cancellation_token = CancellationToken::new();
@@ -83,7 +313,7 @@ async fn http_request_log_uri() {
         let logs: Vec<&str> = all_logs
             .iter()
             .filter(|s| !s.contains("TRACE") && !s.contains("DEBUG"))
-            .map(|&s| s)
+            .copied()
             .collect();
 
         assert_eq!(logs.len(), expected_logs.len());
@@ -113,7 +343,7 @@ async fn http_request_log_uri_headers() {
         let logs: Vec<&str> = all_logs
             .iter()
             .filter(|s| !s.contains("TRACE") && !s.contains("DEBUG"))
-            .map(|&s| s)
+            .copied()
             .collect();
 
         assert_eq!(logs.len(), expected_logs.len());
@@ -144,7 +374,7 @@ async fn http_request_log_uri_headers_body() {
         let logs: Vec<&str> = all_logs
             .iter()
             .filter(|s| !s.contains("TRACE") && !s.contains("DEBUG"))
-            .map(|&s| s)
+            .copied()
             .collect();
 
         assert_eq!(logs.len(), expected_logs.len());
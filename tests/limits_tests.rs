@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use hyper_echo::{ConnectionLimitMode, ConnectionLimits, EchoServer, RequestLimits};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+async fn echo(port: u16) -> Result<String, reqwest::Error> {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/"))
+        .body("some body")
+        .send()
+        .await?
+        .text()
+        .await
+}
+
+#[tokio::test]
+async fn connections_over_limit_are_rejected() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().connection_limits(ConnectionLimits {
+            max_connections: Some(1),
+            mode: ConnectionLimitMode::Reject,
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let idle_connection = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(echo(port).await.is_err());
+
+    drop(idle_connection);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(echo(port).await.unwrap(), "some body");
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn connections_over_limit_wait() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().connection_limits(ConnectionLimits {
+            max_connections: Some(1),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let idle_connection = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let request = tokio::spawn(echo(port));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!request.is_finished());
+
+    drop(idle_connection);
+    let response = tokio::time::timeout(Duration::from_secs(1), request)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.unwrap(), "some body");
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn connections_per_ip_are_limited() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().connection_limits(ConnectionLimits {
+            max_connections_per_ip: Some(1),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let _idle_connection = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(echo(port).await.is_err());
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn invalid_limits() {
+    let result = EchoServer::builder()
+        .connection_limits(ConnectionLimits {
+            max_concurrent_streams: Some(0),
+            ..Default::default()
+        })
+        .build()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn large_body_is_rejected() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().request_limits(RequestLimits {
+            max_body_size: Some(4),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Request body is larger than 4 bytes"
+    );
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/"))
+        .body("body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "body");
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn chunked_body_over_limit_is_aborted() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().request_limits(RequestLimits {
+            max_body_size: Some(4),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\n\r\n\
+              9\r\nsome body\r\n0\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let _ = tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut response))
+        .await
+        .unwrap();
+    // No 413 could be sent as the limit is only exceeded while the body is streamed
+    assert!(response.is_empty());
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn slow_response_times_out() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().request_limits(RequestLimits {
+            request_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/?echo_delay=1000"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::GATEWAY_TIMEOUT);
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn slow_request_times_out() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().request_limits(RequestLimits {
+            request_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"POST /inspect HTTP/1.1\r\nhost: localhost\r\ncontent-length: 10\r\n\r\nabc")
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let read = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&response[..read]).starts_with("HTTP/1.1 408"));
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn idle_connection_is_closed() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().request_limits(RequestLimits {
+            idle_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut buf = [0; 16];
+    let read = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read, 0);
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn header_read_timeout() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().request_limits(RequestLimits {
+            header_read_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let mut buf = [0; 1024];
+    let result = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(result, Ok(0) | Err(_)));
+
+    cancellation_token.cancel();
+}
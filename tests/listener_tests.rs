@@ -0,0 +1,65 @@
+use hyper_echo::EchoServer;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+#[tokio::test]
+async fn http_echo_on_provided_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let echo_server = EchoServer::from_listener(listener).build().await.unwrap();
+    assert_eq!(echo_server.local_addr(), addr);
+    assert_eq!(echo_server.try_local_addr(), Some(addr));
+    assert_eq!(echo_server.local_addrs(), vec![addr]);
+    tokio::spawn(echo_server.run(CancellationToken::new()));
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{addr}/"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "some body");
+}
+
+#[tokio::test]
+async fn ws_echo_on_provided_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = common::spawn_server_with(
+        CancellationToken::new(),
+        EchoServer::from_listener(listener),
+    )
+    .await;
+    let mut ws_client = common::WsClient::connect(port).await;
+
+    ws_client.send_message("Some message").await.unwrap();
+    let (_, response) = ws_client.receive().await.unwrap();
+
+    assert_eq!(response.as_deref(), Some("Some message"));
+}
+
+#[tokio::test]
+async fn bind_addresses_are_ignored_with_provided_listeners() {
+    let first = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let second = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addrs = vec![first.local_addr().unwrap(), second.local_addr().unwrap()];
+
+    let echo_server = EchoServer::builder()
+        .bind_addr("::1".parse().unwrap())
+        .listener(first)
+        .listener(second)
+        .build()
+        .await
+        .unwrap();
+
+    assert_eq!(echo_server.local_addrs(), addrs);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn socket_activation_without_passed_sockets_fails() {
+    let result = EchoServer::builder().socket_activation(true).build().await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,94 @@
+use hyper_echo::EchoServer;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+async fn get_metrics(port: u16) -> String {
+    let response = reqwest::get(format!("http://localhost:{port}/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn http_metrics() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().metrics(true),
+    )
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "some body");
+
+    let metrics = get_metrics(port).await;
+    assert!(metrics.contains(
+        "hyper_echo_http_requests_total{method=\"POST\",status=\"200\",version=\"HTTP/1.1\"} 1"
+    ));
+    assert!(metrics.contains("hyper_echo_http_request_bytes_total 9"));
+    assert!(metrics.contains("hyper_echo_http_response_bytes_total 9"));
+    assert!(metrics.contains("hyper_echo_http_request_duration_seconds_count 1"));
+    assert!(metrics.contains("hyper_echo_active_connections "));
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn ws_metrics() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().metrics(true),
+    )
+    .await;
+
+    let mut ws_client = common::WsClient::connect(port).await;
+    ws_client.send_message("hello").await.unwrap();
+    ws_client.receive().await.unwrap();
+
+    let metrics = get_metrics(port).await;
+    assert!(metrics.contains("hyper_echo_active_ws_sessions 1"));
+    assert!(metrics.contains("hyper_echo_ws_frames_total{opcode=\"text\"} 1"));
+
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{port}/"))
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let metrics = get_metrics(port).await;
+    assert!(metrics.contains("hyper_echo_ws_upgrade_failures_total 1"));
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn metrics_path_is_echoed_when_disabled() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/metrics"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "some body");
+
+    cancellation_token.cancel();
+}
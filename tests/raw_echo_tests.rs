@@ -0,0 +1,141 @@
+use std::{net::SocketAddr, time::Duration};
+
+use hyper_echo::{EchoServer, RawLogLevel};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use tokio_util::sync::CancellationToken;
+use tracing_test::traced_test;
+
+/// Start a server with raw TCP and UDP echo, returns their addresses
+async fn spawn_raw_server(
+    cancellation_token: CancellationToken,
+    log_level: RawLogLevel,
+) -> (SocketAddr, SocketAddr) {
+    let echo_server = EchoServer::builder()
+        .raw_tcp_port(Some(0))
+        .raw_udp_port(Some(0))
+        .raw_log_level(log_level)
+        .build()
+        .await
+        .unwrap();
+    let tcp_addr = echo_server.raw_tcp_addrs()[0];
+    let udp_addr = echo_server.raw_udp_addrs()[0];
+    tokio::spawn(async move {
+        echo_server.run(cancellation_token).await.unwrap();
+    });
+    (tcp_addr, udp_addr)
+}
+
+async fn udp_echo(socket: &UdpSocket, data: &[u8]) -> Vec<u8> {
+    socket.send(data).await.unwrap();
+    let mut buffer = vec![0; 1024];
+    let len = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    buffer.truncate(len);
+    buffer
+}
+
+#[tokio::test]
+async fn tcp_echo() {
+    let (tcp_addr, _) = spawn_raw_server(CancellationToken::new(), RawLogLevel::None).await;
+    let mut stream = TcpStream::connect(tcp_addr).await.unwrap();
+
+    stream.write_all(b"some data").await.unwrap();
+    stream.shutdown().await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+
+    assert_eq!(response, b"some data");
+}
+
+#[tokio::test]
+async fn udp_echo_datagrams() {
+    let (_, udp_addr) = spawn_raw_server(CancellationToken::new(), RawLogLevel::None).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(udp_addr).await.unwrap();
+
+    assert_eq!(udp_echo(&socket, b"first").await, b"first");
+    assert_eq!(udp_echo(&socket, &[0, 255, 16]).await, [0, 255, 16]);
+}
+
+#[tokio::test]
+async fn http_is_served_with_raw_echo() {
+    let echo_server = EchoServer::builder()
+        .raw_tcp_port(Some(0))
+        .build()
+        .await
+        .unwrap();
+    let port = echo_server.local_addr().port();
+    assert_ne!(echo_server.raw_tcp_addrs()[0].port(), port);
+    assert!(echo_server.raw_udp_addrs().is_empty());
+    tokio::spawn(echo_server.run(CancellationToken::new()));
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.text().await.unwrap(), "some body");
+}
+
+#[tokio::test]
+async fn tcp_connection_is_closed_on_shutdown() {
+    let cancellation_token = CancellationToken::new();
+    let (tcp_addr, udp_addr) =
+        spawn_raw_server(cancellation_token.clone(), RawLogLevel::None).await;
+    let mut stream = TcpStream::connect(tcp_addr).await.unwrap();
+    stream.write_all(b"data").await.unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+
+    cancellation_token.cancel();
+
+    let mut rest = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut rest))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(rest.is_empty());
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(udp_addr).await.unwrap();
+    socket.send(b"late").await.unwrap();
+    let mut buffer = [0; 16];
+    let received = tokio::time::timeout(Duration::from_millis(200), socket.recv(&mut buffer)).await;
+    assert!(received.is_err() || received.unwrap().is_err());
+}
+
+#[tokio::test]
+#[traced_test]
+async fn raw_data_is_logged() {
+    let (tcp_addr, udp_addr) = spawn_raw_server(CancellationToken::new(), RawLogLevel::Data).await;
+    let mut stream = TcpStream::connect(tcp_addr).await.unwrap();
+    stream.write_all(b"hello").await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(udp_addr).await.unwrap();
+    udp_echo(&socket, &[0, 255, 16]).await;
+
+    assert!(logs_contain("TCP: connection established"));
+    assert!(logs_contain("TCP: received 5 bytes: hello"));
+    assert!(logs_contain("UDP: received 3 bytes (base64): AP8Q"));
+}
+
+#[tokio::test]
+#[traced_test]
+async fn raw_data_sizes_are_logged() {
+    let (_, udp_addr) = spawn_raw_server(CancellationToken::new(), RawLogLevel::Summary).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(udp_addr).await.unwrap();
+    udp_echo(&socket, b"secret").await;
+
+    assert!(logs_contain("UDP: received 6 bytes"));
+    assert!(!logs_contain("secret"));
+}
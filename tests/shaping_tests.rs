@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+use hyper_echo::{EchoServer, ShapingConfig};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+#[tokio::test]
+async fn http_rate_limit() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().shaping(ShapingConfig {
+            bytes_per_second: Some(1000),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let body = "a".repeat(500);
+    let start = Instant::now();
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .body(body.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), body);
+    assert!(start.elapsed() >= Duration::from_millis(450));
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn http_latency_is_added_once_per_chunk() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().shaping(ShapingConfig {
+            bytes_per_second: Some(1000),
+            chunk_latency: Duration::from_millis(300),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    // The chunk is sent in 5 pieces to keep the rate, the latency is added to the first one only
+    let body = "a".repeat(500);
+    let start = Instant::now();
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .body(body.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), body);
+    assert!(start.elapsed() >= Duration::from_millis(750));
+    assert!(start.elapsed() < Duration::from_millis(1500));
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn http_shaping_ends_on_shutdown() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().shaping(ShapingConfig {
+            bytes_per_second: Some(10),
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let body = "a".repeat(100);
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/"))
+        .body(body.clone())
+        .send()
+        .await
+        .unwrap();
+    cancellation_token.cancel();
+    let text = tokio::time::timeout(Duration::from_secs(2), response.text()).await;
+    assert_eq!(text.unwrap().unwrap(), body);
+}
+
+#[tokio::test]
+async fn http_shaping_by_request() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+
+    let start = Instant::now();
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/?echo_latency=300"))
+        .header("x-echo-rate-bps", "10000")
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "some body");
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/?echo_rate=0"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn ws_shaping() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().shaping(ShapingConfig {
+            chunk_latency: Duration::from_millis(200),
+            websocket: true,
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let mut ws_client = common::WsClient::connect(port).await;
+    let start = Instant::now();
+    ws_client.send_message("hello").await.unwrap();
+    let (_, response) = ws_client.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("hello"));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn ws_shaping_ends_on_shutdown() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server_with(
+        cancellation_token.clone(),
+        EchoServer::builder().shaping(ShapingConfig {
+            chunk_latency: Duration::from_secs(30),
+            websocket: true,
+            ..Default::default()
+        }),
+    )
+    .await;
+
+    let mut ws_client = common::WsClient::connect(port).await;
+    ws_client.send_message("hello").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    cancellation_token.cancel();
+
+    let response = tokio::time::timeout(Duration::from_secs(2), ws_client.receive()).await;
+    let (_, response) = response.unwrap().unwrap();
+    assert_eq!(response.as_deref(), Some("hello"));
+}
+
+#[tokio::test]
+async fn invalid_shaping_config() {
+    let result = EchoServer::builder()
+        .shaping(ShapingConfig {
+            bytes_per_second: Some(0),
+            ..Default::default()
+        })
+        .build()
+        .await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use hyper_echo::{EchoServer, SseConfig};
+use reqwest::StatusCode;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+#[tokio::test]
+async fn sse_body_is_echoed_as_events_followed_by_ticks() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let response = reqwest::Client::new()
+        .post(format!("http://localhost:{port}/sse?interval=10&count=2"))
+        .body("first line\nsecond line\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "event: echo\ndata: first line\n\n\
+         event: echo\ndata: second line\n\n\
+         event: tick\nid: 1\ndata: 1\n\n\
+         event: tick\nid: 2\ndata: 2\n\n"
+    );
+}
+
+#[tokio::test]
+async fn sse_is_selected_by_accept_header() {
+    let builder = EchoServer::builder().sse(SseConfig {
+        interval: Duration::from_millis(10),
+        count: Some(1),
+    });
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{port}/events"))
+        .header("accept", "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "event: tick\nid: 1\ndata: 1\n\n"
+    );
+}
+
+#[tokio::test]
+async fn sse_ticks_are_resumed_after_last_event_id() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{port}/sse?interval=10&count=3"))
+        .header("last-event-id", "1")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.text().await.unwrap(),
+        "event: tick\nid: 2\ndata: 2\n\nevent: tick\nid: 3\ndata: 3\n\n"
+    );
+}
+
+#[tokio::test]
+async fn sse_stream_ends_on_shutdown() {
+    let cancellation_token = CancellationToken::new();
+    let port = common::spawn_server(cancellation_token.clone()).await;
+    let mut response = reqwest::get(format!("http://localhost:{port}/sse?interval=10"))
+        .await
+        .unwrap();
+
+    let chunk = response.chunk().await.unwrap().unwrap();
+    assert_eq!(chunk, "event: tick\nid: 1\ndata: 1\n\n");
+
+    cancellation_token.cancel();
+    let rest = timeout(Duration::from_secs(2), response.text())
+        .await
+        .unwrap();
+    assert!(rest.is_ok());
+}
+
+#[tokio::test]
+async fn sse_invalid_parameters_are_rejected() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("http://localhost:{port}/sse?interval=0"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .get(format!("http://localhost:{port}/sse"))
+        .header("last-event-id", "abc")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
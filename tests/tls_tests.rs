@@ -0,0 +1,226 @@
+#![cfg(feature = "tls")]
+
+use fastwebsockets::OpCode;
+use hyper::Version;
+use hyper_echo::{ClientCertMode, EchoServer, HttpLogLevel, TlsConfig};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+};
+use tokio_util::sync::CancellationToken;
+use tracing_test::traced_test;
+
+mod common;
+
+fn make_client(tls_config: &TlsConfig, http1_only: bool) -> reqwest::Client {
+    let cert = reqwest::Certificate::from_pem(tls_config.certificate_pem().as_bytes()).unwrap();
+    let builder = reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(cert);
+    let builder = if http1_only {
+        builder.http1_only()
+    } else {
+        builder
+    };
+    builder.build().unwrap()
+}
+
+async fn https_echo(http1_only: bool, expected_version: Version) {
+    let tls_config = TlsConfig::self_signed(&["localhost"]).unwrap();
+    let port = common::spawn_tls_server(CancellationToken::new(), tls_config.clone()).await;
+
+    let response = make_client(&tls_config, http1_only)
+        .post(format!("https://localhost:{port}/"))
+        .body("some body")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.version(), expected_version);
+    assert_eq!(response.text().await.unwrap(), "some body");
+}
+
+#[tokio::test]
+async fn https_echo_http1() {
+    https_echo(true, Version::HTTP_11).await;
+}
+
+#[tokio::test]
+async fn https_echo_http2() {
+    https_echo(false, Version::HTTP_2).await;
+}
+
+#[tokio::test]
+async fn plain_http_request_to_tls_server_fails() {
+    let tls_config = TlsConfig::self_signed(&["localhost"]).unwrap();
+    let port = common::spawn_tls_server(CancellationToken::new(), tls_config).await;
+
+    let response = reqwest::get(format!("http://localhost:{port}/")).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn wss_echo() {
+    let tls_config = TlsConfig::self_signed(&["localhost"]).unwrap();
+    let port = common::spawn_tls_server(CancellationToken::new(), tls_config.clone()).await;
+    let mut ws_client = common::WsClient::connect_tls(port, tls_config.certificate_pem()).await;
+    let message = "Some message";
+
+    ws_client.send_message(message).await.unwrap();
+    let (opcode, response) = ws_client.receive().await.unwrap();
+
+    assert_eq!(opcode, OpCode::Text);
+    assert_eq!(response.as_deref(), Some(message));
+}
+
+#[test]
+fn invalid_pem_is_rejected() {
+    assert!(TlsConfig::from_pem(b"not a certificate", b"not a key").is_err());
+}
+
+struct ClientIdentity {
+    ca_pem: String,
+    identity_pem: String,
+}
+
+fn make_client_identity() -> ClientIdentity {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "echo test ca");
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(vec!["client.example.com".to_string()]).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "echo client");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params.signed_by(&client_key, &issuer).unwrap();
+
+    ClientIdentity {
+        ca_pem: ca_cert.pem(),
+        identity_pem: format!("{}{}", client_cert.pem(), client_key.serialize_pem()),
+    }
+}
+
+async fn spawn_mtls_server(
+    client_identity: &ClientIdentity,
+    mode: ClientCertMode,
+) -> (u16, TlsConfig) {
+    let tls_config = TlsConfig::self_signed(&["localhost"])
+        .unwrap()
+        .with_client_auth(client_identity.ca_pem.as_bytes(), mode)
+        .unwrap();
+    let port = common::spawn_tls_server(CancellationToken::new(), tls_config.clone()).await;
+    (port, tls_config)
+}
+
+fn make_mtls_client(tls_config: &TlsConfig, identity_pem: &str) -> reqwest::Client {
+    let cert = reqwest::Certificate::from_pem(tls_config.certificate_pem().as_bytes()).unwrap();
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(cert)
+        .identity(reqwest::Identity::from_pem(identity_pem.as_bytes()).unwrap())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn mtls_echoes_client_certificate() {
+    let client_identity = make_client_identity();
+    let (port, tls_config) = spawn_mtls_server(&client_identity, ClientCertMode::Required).await;
+
+    let response = make_mtls_client(&tls_config, &client_identity.identity_pem)
+        .get(format!("https://localhost:{port}/"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let headers = response.headers();
+    assert_eq!(
+        headers.get("x-echo-client-cert-subject").unwrap(),
+        "CN=echo client"
+    );
+    assert_eq!(
+        headers.get("x-echo-client-cert-san").unwrap(),
+        "DNS:client.example.com"
+    );
+    assert_eq!(
+        headers.get("x-echo-client-cert-fingerprint").unwrap().len(),
+        64
+    );
+}
+
+#[tokio::test]
+#[traced_test]
+async fn mtls_client_certificate_is_in_span() {
+    let client_identity = make_client_identity();
+    let tls_config = TlsConfig::self_signed(&["localhost"])
+        .unwrap()
+        .with_client_auth(client_identity.ca_pem.as_bytes(), ClientCertMode::Required)
+        .unwrap();
+    let port = common::spawn_server_with(
+        CancellationToken::new(),
+        EchoServer::builder()
+            .tls(Some(tls_config.clone()))
+            .http_log_level(HttpLogLevel::Uri),
+    )
+    .await;
+
+    let response = make_mtls_client(&tls_config, &client_identity.identity_pem)
+        .get(format!("https://localhost:{port}/"))
+        .send()
+        .await
+        .unwrap();
+    let fingerprint = response
+        .headers()
+        .get("x-echo-client-cert-fingerprint")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    assert!(logs_contain(&format!(
+        "client_cert=\"CN=echo client\" client_cert_san=\"DNS:client.example.com\" \
+         client_cert_fingerprint=\"{fingerprint}\""
+    )));
+}
+
+#[tokio::test]
+async fn mtls_required_rejects_client_without_certificate() {
+    let client_identity = make_client_identity();
+    let (port, tls_config) = spawn_mtls_server(&client_identity, ClientCertMode::Required).await;
+
+    let response = make_client(&tls_config, false)
+        .get(format!("https://localhost:{port}/"))
+        .send()
+        .await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn mtls_optional_accepts_client_without_certificate() {
+    let client_identity = make_client_identity();
+    let (port, tls_config) = spawn_mtls_server(&client_identity, ClientCertMode::Optional).await;
+
+    let response = make_client(&tls_config, false)
+        .get(format!("https://localhost:{port}/"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert!(
+        response
+            .headers()
+            .get("x-echo-client-cert-subject")
+            .is_none()
+    );
+}
@@ -0,0 +1,230 @@
+#![cfg(unix)]
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use fastwebsockets::OpCode;
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, body::Bytes};
+use hyper_echo::{EchoServer, HttpLogLevel};
+use hyper_util::rt::TokioIo;
+use serde_json::Value;
+use tokio::net::UnixStream;
+use tokio_util::sync::CancellationToken;
+use tracing_test::traced_test;
+
+mod common;
+
+/// Unique socket path in the temp directory, removed if left by a previous run
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("hyper_echo_{}_{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+async fn spawn_unix_server(path: &Path, cancellation_token: CancellationToken) {
+    common::spawn_server_with(
+        cancellation_token,
+        EchoServer::builder()
+            .unix_socket(Some(path.to_path_buf()))
+            .http_log_level(HttpLogLevel::Uri),
+    )
+    .await;
+}
+
+/// Send a request over the Unix socket, returns status and body of the response
+async fn unix_request(path: &Path, uri: &str, body: &'static str) -> (u16, Bytes) {
+    let stream = UnixStream::connect(path).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+
+    let request = Request::post(uri)
+        .header("host", "localhost")
+        .body(Full::new(Bytes::from_static(body.as_bytes())))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    let status = response.status().as_u16();
+    (status, response.collect().await.unwrap().to_bytes())
+}
+
+#[tokio::test]
+async fn http_echo_over_unix_socket() {
+    let path = socket_path("http");
+    spawn_unix_server(&path, CancellationToken::new()).await;
+
+    let (status, body) = unix_request(&path, "/", "some body").await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body, "some body");
+}
+
+#[tokio::test]
+async fn ws_echo_over_unix_socket() {
+    let path = socket_path("ws");
+    spawn_unix_server(&path, CancellationToken::new()).await;
+    let mut ws_client = common::WsClient::connect_unix(&path).await;
+
+    ws_client.send_message("Some message").await.unwrap();
+    let (opcode, response) = ws_client.receive().await.unwrap();
+
+    assert_eq!(opcode, OpCode::Text);
+    assert_eq!(response.as_deref(), Some("Some message"));
+}
+
+#[tokio::test]
+async fn inspect_reports_peer_credentials() {
+    let path = socket_path("inspect");
+    spawn_unix_server(&path, CancellationToken::new()).await;
+
+    let (status, body) = unix_request(&path, "/inspect", "").await;
+
+    assert_eq!(status, 200);
+    let document: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(document["client"]["pid"], std::process::id());
+    assert!(document["client"]["uid"].is_u64());
+    assert!(document["client"].get("ip").is_none());
+}
+
+#[tokio::test]
+#[traced_test]
+async fn span_shows_peer_credentials() {
+    let path = socket_path("span");
+    spawn_unix_server(&path, CancellationToken::new()).await;
+
+    unix_request(&path, "/", "").await;
+
+    assert!(logs_contain(&format!(
+        "client{{pid={} uid=",
+        std::process::id()
+    )));
+}
+
+#[tokio::test]
+async fn stale_socket_is_replaced() {
+    let path = socket_path("stale");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    spawn_unix_server(&path, CancellationToken::new()).await;
+
+    let (status, _) = unix_request(&path, "/", "").await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn socket_in_use_is_not_replaced() {
+    let path = socket_path("in_use");
+    spawn_unix_server(&path, CancellationToken::new()).await;
+
+    let result = EchoServer::builder()
+        .unix_socket(Some(path.clone()))
+        .build()
+        .await;
+
+    assert!(result.is_err());
+    let (status, _) = unix_request(&path, "/", "").await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn regular_file_is_not_replaced() {
+    let path = socket_path("regular_file");
+    std::fs::write(&path, "data").unwrap();
+
+    let result = EchoServer::builder()
+        .unix_socket(Some(path.clone()))
+        .build()
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn socket_mode_is_applied() {
+    let dir = std::env::temp_dir().join(format!("hyper_echo_{}_mode", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    let path = dir.join("echo.sock");
+    let echo_server = EchoServer::builder()
+        .unix_socket(Some(path.clone()))
+        .unix_socket_mode(Some(0o600))
+        .build()
+        .await
+        .unwrap();
+
+    assert_eq!(echo_server.unix_socket_path(), Some(path.as_path()));
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    // The socket is bound in a temporary directory which is removed after moving the socket
+    let files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files, std::slice::from_ref(&path));
+
+    let cancellation_token = CancellationToken::new();
+    tokio::spawn(echo_server.run(cancellation_token.clone()));
+    let (status, body) = unix_request(&path, "/", "mode").await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "mode");
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn invalid_socket_mode_is_rejected() {
+    let result = EchoServer::builder()
+        .unix_socket(Some(socket_path("invalid_mode")))
+        .unix_socket_mode(Some(0o1777))
+        .build()
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn socket_file_is_removed_after_shutdown() {
+    let path = socket_path("shutdown");
+    let echo_server = EchoServer::builder()
+        .unix_socket(Some(path.clone()))
+        .build()
+        .await
+        .unwrap();
+    let cancellation_token = CancellationToken::new();
+    let server = tokio::spawn(echo_server.run(cancellation_token.clone()));
+    assert!(path.exists());
+
+    cancellation_token.cancel();
+    server.await.unwrap().unwrap();
+
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn provided_listener_is_served_and_kept() {
+    let path = socket_path("provided");
+    let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+    let echo_server = EchoServer::builder()
+        .unix_listener(listener)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(echo_server.unix_socket_paths(), vec![path.as_path()]);
+    assert_eq!(echo_server.try_local_addr(), None);
+    let cancellation_token = CancellationToken::new();
+    let server = tokio::spawn(echo_server.run(cancellation_token.clone()));
+
+    let (status, body) = unix_request(&path, "/", "some body").await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "some body");
+
+    cancellation_token.cancel();
+    server.await.unwrap().unwrap();
+    assert!(path.exists());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,182 @@
+use fastwebsockets::WebSocket;
+use http_body_util::Empty;
+use hyper::{
+    Request,
+    body::Bytes,
+    header::{CONNECTION, SEC_WEBSOCKET_EXTENSIONS, UPGRADE},
+    upgrade::Upgraded,
+};
+use hyper_echo::{DeflateConfig, EchoServer};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+const RSV1: u8 = 0x40;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+
+/// Connects offering `extensions` and returns the raw stream and the negotiated extensions
+async fn connect(port: u16, extensions: &str) -> (TokioIo<Upgraded>, Option<String>) {
+    let host = format!("localhost:{port}");
+    let stream = TcpStream::connect(&host).await.unwrap();
+    let request = Request::builder()
+        .uri(format!("http://{host}/"))
+        .header("Host", &host)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "upgrade")
+        .header(
+            "Sec-WebSocket-Key",
+            fastwebsockets::handshake::generate_key(),
+        )
+        .header("Sec-WebSocket-Version", "13")
+        .header(SEC_WEBSOCKET_EXTENSIONS, extensions)
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (ws, response): (WebSocket<_>, _) =
+        fastwebsockets::handshake::client(&TokioExecutor::new(), request, stream)
+            .await
+            .unwrap();
+    let negotiated = response
+        .headers()
+        .get(SEC_WEBSOCKET_EXTENSIONS)
+        .map(|value| value.to_str().unwrap().to_string());
+    (ws.into_inner(), negotiated)
+}
+
+async fn write_frame(stream: &mut TokioIo<Upgraded>, first_byte: u8, payload: &[u8]) {
+    assert!(payload.len() < 126);
+    let mask = [1, 2, 3, 4];
+    let mut frame = vec![first_byte, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).await.unwrap();
+}
+
+/// Reads a frame sent by the server. Returns its first byte and payload.
+async fn read_frame(stream: &mut TokioIo<Upgraded>) -> (u8, Vec<u8>) {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header).await.unwrap();
+    let len = match header[1] & 0x7f {
+        126 => stream.read_u16().await.unwrap() as usize,
+        127 => stream.read_u64().await.unwrap() as usize,
+        len => len as usize,
+    };
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).await.unwrap();
+    (header[0], payload)
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    compress_to_vec(data, 6)
+}
+
+fn decompress(payload: &[u8]) -> Vec<u8> {
+    let mut data = payload.to_vec();
+    // Restore the omitted tail and end the stream with an empty final block
+    data.extend_from_slice(&[0x00, 0x00, 0xff, 0xff, 0x03, 0x00]);
+    decompress_to_vec(&data).unwrap()
+}
+
+async fn spawn_server(config: DeflateConfig) -> u16 {
+    let builder = EchoServer::builder().ws_deflate(Some(config));
+    common::spawn_server_with(CancellationToken::new(), builder).await
+}
+
+#[tokio::test]
+async fn ws_compressed_messages_are_echoed_compressed() {
+    let port = spawn_server(DeflateConfig::default()).await;
+    let (mut stream, extensions) = connect(port, "permessage-deflate").await;
+    assert_eq!(extensions.as_deref(), Some("permessage-deflate"));
+
+    for message in ["Hello, hello, hello", "Another message"] {
+        write_frame(
+            &mut stream,
+            0x80 | RSV1 | TEXT,
+            &compress(message.as_bytes()),
+        )
+        .await;
+        let (first_byte, payload) = read_frame(&mut stream).await;
+        assert_eq!(first_byte, 0x80 | RSV1 | TEXT);
+        assert_eq!(decompress(&payload), message.as_bytes());
+    }
+
+    // Uncompressed messages are accepted too
+    write_frame(&mut stream, 0x80 | BINARY, &[0xff, 0x00]).await;
+    let (first_byte, payload) = read_frame(&mut stream).await;
+    assert_eq!(first_byte, 0x80 | RSV1 | BINARY);
+    assert_eq!(decompress(&payload), [0xff, 0x00]);
+}
+
+#[tokio::test]
+async fn ws_compressed_frames_are_counted_by_opcode() {
+    let builder = EchoServer::builder()
+        .ws_deflate(Some(DeflateConfig::default()))
+        .metrics(true);
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let (mut stream, _) = connect(port, "permessage-deflate").await;
+
+    write_frame(&mut stream, 0x80 | RSV1 | TEXT, &compress(b"Hello")).await;
+    read_frame(&mut stream).await;
+
+    let metrics = reqwest::get(format!("http://localhost:{port}/metrics"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("hyper_echo_ws_frames_total{opcode=\"text\"} 1"));
+    assert!(!metrics.contains("hyper_echo_ws_frames_total{opcode=\"binary\"}"));
+}
+
+#[tokio::test]
+async fn ws_deflate_parameters_are_negotiated() {
+    let config = DeflateConfig {
+        server_max_window_bits: 10,
+        client_max_window_bits: Some(12),
+        server_no_context_takeover: true,
+        client_no_context_takeover: false,
+    };
+    let port = spawn_server(config).await;
+
+    let (_, extensions) = connect(
+        port,
+        "permessage-deflate; client_max_window_bits=9, permessage-deflate",
+    )
+    .await;
+    assert_eq!(
+        extensions.as_deref(),
+        Some(
+            "permessage-deflate; server_no_context_takeover; server_max_window_bits=10; \
+             client_max_window_bits=9"
+        )
+    );
+
+    let (_, extensions) = connect(port, "permessage-deflate").await;
+    assert_eq!(extensions, None);
+}
+
+#[tokio::test]
+async fn ws_deflate_is_not_negotiated_when_disabled() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let (_, extensions) = connect(port, "permessage-deflate").await;
+    assert_eq!(extensions, None);
+}
+
+#[tokio::test]
+async fn ws_invalid_compressed_message_closes_session() {
+    let port = spawn_server(DeflateConfig::default()).await;
+    let (mut stream, _) = connect(port, "permessage-deflate").await;
+
+    write_frame(&mut stream, 0x80 | RSV1 | BINARY, &[0xff, 0xff, 0xff]).await;
+    let (first_byte, payload) = read_frame(&mut stream).await;
+
+    assert_eq!(first_byte & 0x0f, CLOSE);
+    assert_eq!(u16::from_be_bytes([payload[0], payload[1]]), 1007);
+}
@@ -0,0 +1,111 @@
+use fastwebsockets::OpCode;
+use hyper_echo::{EchoServer, WsFragmentMode};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+async fn send_fragmented(ws_client: &mut common::RawWsClient) {
+    ws_client
+        .send_frame(false, OpCode::Text, b"Hello, ")
+        .await
+        .unwrap();
+    ws_client
+        .send_frame(false, OpCode::Continuation, b"fragmented ")
+        .await
+        .unwrap();
+    ws_client
+        .send_frame(true, OpCode::Continuation, b"world")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn ws_fragmented_message_is_reassembled() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::RawWsClient::connect(port, "/").await;
+
+    send_fragmented(&mut ws_client).await;
+    let frame = ws_client.receive_frame().await.unwrap();
+
+    assert_eq!(
+        frame,
+        (true, OpCode::Text, b"Hello, fragmented world".to_vec())
+    );
+}
+
+#[tokio::test]
+async fn ws_fragmentation_is_mirrored() {
+    let builder = EchoServer::builder().ws_fragment_mode(WsFragmentMode::Mirror);
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut ws_client = common::RawWsClient::connect(port, "/").await;
+
+    send_fragmented(&mut ws_client).await;
+
+    let expected = [
+        (false, OpCode::Text, b"Hello, ".to_vec()),
+        (false, OpCode::Continuation, b"fragmented ".to_vec()),
+        (true, OpCode::Continuation, b"world".to_vec()),
+    ];
+    for expected in expected {
+        assert_eq!(ws_client.receive_frame().await.unwrap(), expected);
+    }
+}
+
+#[tokio::test]
+async fn ws_fragment_mode_is_selected_per_connection() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::RawWsClient::connect(port, "/?fragments=mirror").await;
+
+    send_fragmented(&mut ws_client).await;
+
+    let (fin, opcode, payload) = ws_client.receive_frame().await.unwrap();
+    assert!(!fin);
+    assert_eq!(opcode, OpCode::Text);
+    assert_eq!(payload, b"Hello, ");
+}
+
+#[tokio::test]
+async fn ws_fragmented_message_over_limit_closes_session() {
+    let builder = EchoServer::builder()
+        .ws_max_message_size(16)
+        .ws_max_frame_size(Some(16));
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut ws_client = common::RawWsClient::connect(port, "/").await;
+
+    send_fragmented(&mut ws_client).await;
+    let (_, opcode, payload) = ws_client.receive_frame().await.unwrap();
+
+    assert_eq!(opcode, OpCode::Close);
+    assert_eq!(u16::from_be_bytes([payload[0], payload[1]]), 1009);
+}
+
+#[tokio::test]
+async fn ws_unexpected_continuation_closes_session() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::RawWsClient::connect(port, "/").await;
+
+    ws_client
+        .send_frame(true, OpCode::Continuation, b"orphan")
+        .await
+        .unwrap();
+    let (_, opcode, payload) = ws_client.receive_frame().await.unwrap();
+
+    assert_eq!(opcode, OpCode::Close);
+    assert_eq!(u16::from_be_bytes([payload[0], payload[1]]), 1002);
+}
+
+#[tokio::test]
+async fn ws_invalid_fragment_mode_is_rejected() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let response = reqwest::Client::new()
+        .get(format!("http://localhost:{port}/?fragments=shuffle"))
+        .header("upgrade", "websocket")
+        .header("connection", "upgrade")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("sec-websocket-version", "13")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,79 @@
+use fastwebsockets::{Frame, OpCode, Payload, Role, WebSocket};
+use http_body_util::Empty;
+use hyper::{
+    Method, Request, Response, StatusCode, Version,
+    body::{Bytes, Incoming},
+    client::conn::http2::SendRequest,
+    ext::Protocol,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+/// Open an HTTP/2 connection with prior knowledge
+async fn h2_connect(port: u16) -> SendRequest<Empty<Bytes>> {
+    let stream = TcpStream::connect(format!("localhost:{port}"))
+        .await
+        .unwrap();
+    let (mut sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(connection);
+    sender.ready().await.unwrap();
+    sender
+}
+
+async fn extended_connect(
+    sender: &mut SendRequest<Empty<Bytes>>,
+    port: u16,
+    path: &str,
+    version: &str,
+) -> Response<Incoming> {
+    let request = Request::builder()
+        .method(Method::CONNECT)
+        .version(Version::HTTP_2)
+        .uri(format!("http://localhost:{port}{path}"))
+        .header("sec-websocket-version", version)
+        .extension(Protocol::from_static("websocket"))
+        .body(Empty::new())
+        .unwrap();
+    sender.send_request(request).await.unwrap()
+}
+
+#[tokio::test]
+async fn ws_over_h2_is_echoed() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut sender = h2_connect(port).await;
+
+    // Several WebSockets could share one connection
+    let mut sessions = Vec::new();
+    for path in ["/", "/ws/uppercase"] {
+        let mut response = extended_connect(&mut sender, port, path, "13").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let upgraded = hyper::upgrade::on(&mut response).await.unwrap();
+        sessions.push(WebSocket::after_handshake(
+            TokioIo::new(upgraded),
+            Role::Client,
+        ));
+    }
+
+    for (ws, expected) in sessions.iter_mut().zip(["Hello, h2", "HELLO, H2"]) {
+        ws.write_frame(Frame::text(Payload::Borrowed(b"Hello, h2")))
+            .await
+            .unwrap();
+        let frame = ws.read_frame().await.unwrap();
+        assert_eq!(frame.opcode, OpCode::Text);
+        assert_eq!(&frame.payload[..], expected.as_bytes());
+    }
+}
+
+#[tokio::test]
+async fn ws_over_h2_with_unsupported_version_is_rejected() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut sender = h2_connect(port).await;
+    let response = extended_connect(&mut sender, port, "/", "8").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
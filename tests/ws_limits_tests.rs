@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use fastwebsockets::OpCode;
+use hyper_echo::EchoServer;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+fn close_code(payload: &[u8]) -> u16 {
+    u16::from_be_bytes([payload[0], payload[1]])
+}
+
+#[tokio::test]
+async fn ws_message_over_limit_closes_session() {
+    let builder = EchoServer::builder().ws_max_message_size(4);
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+
+    ws_client.send_message("abc").await.unwrap();
+    let (opcode, response) = ws_client.receive().await.unwrap();
+    assert_eq!(opcode, OpCode::Text);
+    assert_eq!(response.as_deref(), Some("abc"));
+
+    ws_client.send_message("too long").await.unwrap();
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+    assert_eq!(opcode, OpCode::Close);
+    assert_eq!(close_code(&payload), 1009);
+    assert_eq!(&payload[2..], b"Message too big");
+}
+
+#[tokio::test]
+async fn ws_limit_close_code_is_configurable() {
+    let builder = EchoServer::builder()
+        .ws_max_message_size(1024)
+        .ws_max_frame_size(Some(4))
+        .ws_limit_close(4000, "nope");
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+
+    ws_client.send_message("too long").await.unwrap();
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+    assert_eq!(opcode, OpCode::Close);
+    assert_eq!(close_code(&payload), 4000);
+    assert_eq!(&payload[2..], b"nope");
+}
+
+#[tokio::test]
+async fn ws_auto_pong_can_be_disabled() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+    ws_client.send_ping().await.unwrap();
+    let (opcode, _) = ws_client.receive().await.unwrap();
+    assert_eq!(opcode, OpCode::Pong);
+
+    let builder = EchoServer::builder().ws_auto_pong(false);
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+    ws_client.send_ping().await.unwrap();
+    let response = timeout(Duration::from_millis(200), ws_client.receive()).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn ws_session_closed_after_pong_timeout() {
+    let builder = EchoServer::builder()
+        .ws_ping_interval(Some(Duration::from_millis(50)))
+        .ws_pong_timeout(Some(Duration::from_millis(400)));
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+
+    // Pings are not answered, but the session lives until the pong timeout
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    ws_client.send_message("still alive").await.unwrap();
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+    assert_eq!(opcode, OpCode::Text);
+    assert_eq!(payload, b"still alive");
+
+    let (opcode, _) = timeout(Duration::from_secs(2), ws_client.receive_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(opcode, OpCode::Close);
+}
+
+#[tokio::test]
+async fn ws_invalid_options_are_rejected() {
+    let builders = [
+        EchoServer::builder().ws_max_frame_size(Some(0)),
+        EchoServer::builder().ws_pong_timeout(Some(Duration::ZERO)),
+        EchoServer::builder().ws_limit_close(1005, "reserved"),
+        EchoServer::builder().ws_limit_close(1009, &"x".repeat(124)),
+    ];
+    for builder in builders {
+        assert!(builder.build().await.is_err());
+    }
+}
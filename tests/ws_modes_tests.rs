@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use fastwebsockets::{OpCode, Role, WebSocket};
+use tokio::{io::AsyncWriteExt, time::timeout};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+#[tokio::test]
+async fn ws_messages_are_transformed() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+
+    let mut ws_client = common::WsClient::connect_to(port, "/ws/uppercase").await;
+    ws_client.send_message("Hello, world").await.unwrap();
+    let (_, response) = ws_client.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("HELLO, WORLD"));
+
+    let mut ws_client = common::WsClient::connect_to(port, "/ws?mode=reverse").await;
+    ws_client.send_message("Hello, world").await.unwrap();
+    let (_, response) = ws_client.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("dlrow ,olleH"));
+    ws_client
+        .send_frame(OpCode::Binary, &[1, 2, 3])
+        .await
+        .unwrap();
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+    assert_eq!(opcode, OpCode::Binary);
+    assert_eq!(payload, [3, 2, 1]);
+}
+
+#[tokio::test]
+async fn ws_messages_are_broadcast_to_all_sessions() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut sender = common::WsClient::connect_to(port, "/ws/broadcast").await;
+    let mut receiver = common::WsClient::connect_to(port, "/ws/broadcast").await;
+    let mut echo_client = common::WsClient::connect(port).await;
+
+    sender.send_message("Hello, everyone").await.unwrap();
+    for ws_client in [&mut sender, &mut receiver] {
+        let (opcode, response) = ws_client.receive().await.unwrap();
+        assert_eq!(opcode, OpCode::Text);
+        assert_eq!(response.as_deref(), Some("Hello, everyone"));
+    }
+
+    // Sessions in other modes don't receive broadcast messages
+    let response = timeout(Duration::from_millis(200), echo_client.receive()).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn ws_periodic_messages_are_sent() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client =
+        common::WsClient::connect_to(port, "/ws?mode=periodic&interval=20&count=3").await;
+
+    for n in 1..=3 {
+        let (_, response) = ws_client.receive().await.unwrap();
+        assert_eq!(response, Some(format!("message {n}")));
+    }
+    let response = timeout(Duration::from_millis(200), ws_client.receive()).await;
+    assert!(response.is_err());
+
+    // Messages are still echoed
+    ws_client.send_message("echo").await.unwrap();
+    let (_, response) = ws_client.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("echo"));
+}
+
+#[tokio::test]
+async fn ws_frame_arriving_in_pieces_is_not_broken_by_periodic_messages() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut stream = common::connect_ws_stream(port, "/ws?mode=periodic&interval=10&count=5").await;
+
+    let message = vec![7_u8; 10_000];
+    let frame = common::client_frame_bytes(OpCode::Binary, &message);
+    for piece in frame.chunks(frame.len() / 5 + 1) {
+        stream.write_all(piece).await.unwrap();
+        stream.flush().await.unwrap();
+        // Periodic messages are sent while the frame is read
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let mut ws = WebSocket::after_handshake(stream, Role::Client);
+    let mut periodic = 0;
+    loop {
+        let frame = ws.read_frame().await.unwrap();
+        if frame.opcode == OpCode::Binary {
+            assert_eq!(frame.payload.to_vec(), message);
+            break;
+        }
+        periodic += 1;
+        assert_eq!(
+            frame.payload.to_vec(),
+            format!("message {periodic}").into_bytes()
+        );
+    }
+    assert!(periodic > 0);
+}
+
+#[tokio::test]
+async fn ws_session_is_closed_after_messages() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client =
+        common::WsClient::connect_to(port, "/ws/close?after=2&code=4001&reason=Go+away").await;
+
+    for message in ["first", "second"] {
+        ws_client.send_message(message).await.unwrap();
+        let (_, response) = ws_client.receive().await.unwrap();
+        assert_eq!(response.as_deref(), Some(message));
+    }
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+    assert_eq!(opcode, OpCode::Close);
+    assert_eq!(u16::from_be_bytes([payload[0], payload[1]]), 4001);
+    assert_eq!(&payload[2..], b"Go away");
+}
+
+#[tokio::test]
+async fn ws_connection_is_dropped_without_close_frame() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::RawWsClient::connect(port, "/ws/drop?after=1").await;
+
+    ws_client
+        .send_frame(true, OpCode::Text, b"last")
+        .await
+        .unwrap();
+    let (_, opcode, payload) = ws_client.receive_frame().await.unwrap();
+    assert_eq!(opcode, OpCode::Text);
+    assert_eq!(payload, b"last");
+    assert!(ws_client.receive_frame().await.is_err());
+}
+
+#[tokio::test]
+async fn ws_invalid_mode_is_rejected() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    for path in [
+        "/ws?mode=unknown",
+        "/ws?mode=close&code=1006",
+        "/?mode=periodic&interval=x",
+    ] {
+        let response = reqwest::Client::new()
+            .get(format!("http://localhost:{port}{path}"))
+            .header("upgrade", "websocket")
+            .header("connection", "upgrade")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header("sec-websocket-version", "13")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn ws_unknown_mode_path_is_echoed() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    for path in ["/ws/foo", "/ws/foo/bar", "/ws?room=chat"] {
+        let mut ws_client = common::WsClient::connect_to(port, path).await;
+        ws_client.send_message("Hello, world").await.unwrap();
+        let (_, response) = ws_client.receive().await.unwrap();
+        assert_eq!(response.as_deref(), Some("Hello, world"));
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use fastwebsockets::{OpCode, Role, WebSocket};
+use hyper_echo::EchoServer;
+use tokio::{io::AsyncWriteExt, time::timeout};
+use tokio_util::sync::CancellationToken;
+use tracing_test::traced_test;
+
+mod common;
+
+async fn assert_no_message(ws_client: &mut common::WsClient) {
+    let response = timeout(Duration::from_millis(200), ws_client.receive()).await;
+    assert!(response.is_err());
+}
+
+#[tokio::test]
+async fn ws_messages_are_sent_to_room_members_only() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut alice = common::WsClient::connect_to(port, "/ws/broadcast/chat").await;
+    let mut bob = common::WsClient::connect_to(port, "/ws?mode=broadcast&room=chat").await;
+    let mut carol = common::WsClient::connect_to(port, "/ws/broadcast/other").await;
+
+    alice.send_message("Hi, Bob").await.unwrap();
+    for ws_client in [&mut alice, &mut bob] {
+        let (_, response) = ws_client.receive().await.unwrap();
+        assert_eq!(response.as_deref(), Some("Hi, Bob"));
+    }
+    assert_no_message(&mut carol).await;
+
+    bob.send_message("Hi, Alice").await.unwrap();
+    let (_, response) = alice.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("Hi, Alice"));
+}
+
+#[tokio::test]
+async fn ws_sender_could_be_excluded_from_broadcast() {
+    let builder = EchoServer::builder().ws_broadcast_to_sender(false);
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let mut alice = common::WsClient::connect_to(port, "/ws/broadcast/chat").await;
+    let mut bob = common::WsClient::connect_to(port, "/ws/broadcast/chat").await;
+
+    alice.send_message("Hi, Bob").await.unwrap();
+    let (_, response) = bob.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some("Hi, Bob"));
+    assert_no_message(&mut alice).await;
+}
+
+#[tokio::test]
+async fn ws_frame_arriving_in_pieces_is_not_broken_by_broadcasts() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut alice = common::connect_ws_stream(port, "/ws/broadcast/chat").await;
+    let mut bob = common::WsClient::connect_to(port, "/ws/broadcast/chat").await;
+
+    let message: String = (0..100_000)
+        .map(|i| char::from(b'a' + (i % 26) as u8))
+        .collect();
+    let frame = common::client_frame_bytes(OpCode::Text, message.as_bytes());
+    let pieces = [1, 5, 14, 1000, 50_000, frame.len()];
+    let mut written = 0;
+    for (i, end) in pieces.into_iter().enumerate() {
+        alice.write_all(&frame[written..end]).await.unwrap();
+        alice.flush().await.unwrap();
+        written = end;
+        if end == frame.len() {
+            break;
+        }
+        // Alice's session sends the broadcast while it is in the middle of reading the frame
+        bob.send_message(&format!("message {i}")).await.unwrap();
+        let (_, response) = bob.receive().await.unwrap();
+        assert_eq!(response, Some(format!("message {i}")));
+    }
+
+    let (_, response) = bob.receive().await.unwrap();
+    assert_eq!(response.as_deref(), Some(message.as_str()));
+
+    let mut alice = WebSocket::after_handshake(alice, Role::Client);
+    for i in 0..pieces.len() - 1 {
+        let frame = alice.read_frame().await.unwrap();
+        assert_eq!(frame.payload.to_vec(), format!("message {i}").into_bytes());
+    }
+    let frame = alice.read_frame().await.unwrap();
+    assert_eq!(frame.payload.to_vec(), message.into_bytes());
+}
+
+#[tokio::test]
+#[traced_test]
+async fn ws_room_join_and_leave_are_logged() {
+    let builder = EchoServer::builder().ws_logging(true);
+    let port = common::spawn_server_with(CancellationToken::new(), builder).await;
+    let alice = common::WsClient::connect_to(port, "/ws/broadcast/chat").await;
+    let mut bob = common::WsClient::connect_to(port, "/ws/broadcast/chat").await;
+
+    drop(alice);
+    // The room is still alive, so the message is delivered
+    bob.send_message("Anyone?").await.unwrap();
+    bob.receive().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(logs_contain("WS: joined room chat (1 members)"));
+    assert!(logs_contain("WS: joined room chat (2 members)"));
+    assert!(logs_contain("WS: left room chat"));
+}
@@ -0,0 +1,80 @@
+use hyper_echo::{EchoServer, WsSubprotocols};
+use reqwest::{Response, StatusCode};
+use tokio_util::sync::CancellationToken;
+use tracing_test::traced_test;
+
+mod common;
+
+async fn upgrade(port: u16, protocols: &str) -> Response {
+    reqwest::Client::new()
+        .get(format!("http://localhost:{port}/"))
+        .header("upgrade", "websocket")
+        .header("connection", "upgrade")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-protocol", protocols)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn selected(response: &Response) -> Option<&str> {
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    response
+        .headers()
+        .get("sec-websocket-protocol")
+        .map(|value| value.to_str().unwrap())
+}
+
+async fn spawn_server(subprotocols: WsSubprotocols) -> u16 {
+    let builder = EchoServer::builder()
+        .ws_subprotocols(subprotocols)
+        .ws_logging(true);
+    common::spawn_server_with(CancellationToken::new(), builder).await
+}
+
+#[tokio::test]
+async fn ws_subprotocol_is_not_selected_by_default() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let response = upgrade(port, "graphql-ws").await;
+    assert_eq!(selected(&response), None);
+}
+
+#[tokio::test]
+async fn ws_first_supported_subprotocol_is_selected() {
+    let supported = vec!["mqtt".to_string(), "graphql-transport-ws".to_string()];
+    let port = spawn_server(WsSubprotocols::Supported(supported)).await;
+
+    let response = upgrade(port, "graphql-ws, graphql-transport-ws, mqtt").await;
+    assert_eq!(selected(&response), Some("graphql-transport-ws"));
+
+    let response = upgrade(port, "wamp").await;
+    assert_eq!(selected(&response), None);
+}
+
+#[tokio::test]
+async fn ws_first_offered_subprotocol_is_echoed() {
+    let port = spawn_server(WsSubprotocols::EchoFirst).await;
+    let response = upgrade(port, "chat.v2, chat").await;
+    assert_eq!(selected(&response), Some("chat.v2"));
+}
+
+#[tokio::test]
+#[traced_test]
+async fn ws_selected_subprotocol_is_logged() {
+    let port = spawn_server(WsSubprotocols::EchoFirst).await;
+    let _response = upgrade(port, "mqtt").await;
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    assert!(logs_contain("subprotocol=\"mqtt\""));
+}
+
+#[tokio::test]
+async fn ws_invalid_subprotocol_is_rejected() {
+    let subprotocols = WsSubprotocols::Supported(vec!["with space".to_string()]);
+    let result = EchoServer::builder()
+        .ws_subprotocols(subprotocols)
+        .build()
+        .await;
+    assert!(result.is_err());
+}
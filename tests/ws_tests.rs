@@ -17,7 +17,7 @@ async fn ws_echo_test() {
     let (opcode, response) = ws_client.receive().await.unwrap();
 
     assert_eq!(opcode, OpCode::Text);
-    assert_eq!(response.as_ref().map(String::as_str), Some(message));
+    assert_eq!(response.as_deref(), Some(message));
 }
 
 #[tokio::test]
@@ -30,7 +30,7 @@ async fn ws_echo_multiple_messages_test() {
         let (opcode, response) = ws_client.receive().await.unwrap();
 
         assert_eq!(opcode, OpCode::Text);
-        assert_eq!(response.as_ref().map(String::as_str), Some(message));
+        assert_eq!(response.as_deref(), Some(message));
     }
 }
 
@@ -67,7 +67,7 @@ async fn ws_echo_with_pings() {
     let (opcode, response) = ws_client.receive().await.unwrap();
 
     assert_eq!(opcode, OpCode::Text);
-    assert_eq!(response.as_ref().map(String::as_str), Some(message));
+    assert_eq!(response.as_deref(), Some(message));
 }
 
 #[tokio::test]
@@ -81,8 +81,6 @@ async fn ws_client_is_disconnected_when_doesnt_send_pongs() {
     assert_eq!(opcode, OpCode::Ping);
     assert_eq!(data, None);
 
-    let _ = tokio::time::sleep(Duration::from_millis(11));
-
     let (opcode, data) = ws_client.receive().await.unwrap();
     assert_eq!(opcode, OpCode::Close);
     assert_eq!(data, None);
@@ -131,7 +129,7 @@ async fn ws_echo_logging_enabled() {
         let logs: Vec<&str> = all_logs
             .iter()
             .filter(|s| !s.contains("TRACE") && !s.contains("DEBUG"))
-            .map(|&s| s)
+            .copied()
             .collect();
 
         assert_eq!(logs.len(), expected_logs.len());
@@ -143,3 +141,51 @@ async fn ws_echo_logging_enabled() {
         Ok(())
     });
 }
+
+#[tokio::test]
+async fn ws_binary_message_is_echoed_exactly() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+    let message = [0x00, 0xff, 0xfe, 0x80, 0x41, 0xc3];
+
+    ws_client
+        .send_frame(OpCode::Binary, &message)
+        .await
+        .unwrap();
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+
+    assert_eq!(opcode, OpCode::Binary);
+    assert_eq!(payload, message);
+}
+
+#[tokio::test]
+async fn ws_invalid_utf8_text_closes_session() {
+    let port = common::spawn_server(CancellationToken::new()).await;
+    let mut ws_client = common::WsClient::connect(port).await;
+
+    ws_client
+        .send_frame(OpCode::Text, &[0x41, 0xff, 0xfe])
+        .await
+        .unwrap();
+    let (opcode, payload) = ws_client.receive_raw().await.unwrap();
+
+    assert_eq!(opcode, OpCode::Close);
+    assert_eq!(u16::from_be_bytes([payload[0], payload[1]]), 1007);
+}
+
+#[tokio::test]
+#[traced_test]
+async fn ws_binary_message_is_logged_as_base64() {
+    let port =
+        common::spawn_server_with_log_level(CancellationToken::new(), HttpLogLevel::None, true)
+            .await;
+    let mut ws_client = common::WsClient::connect(port).await;
+
+    ws_client
+        .send_frame(OpCode::Binary, &[0x00, 0xff, 0x10])
+        .await
+        .unwrap();
+    ws_client.receive_raw().await.unwrap();
+
+    assert!(logs_contain("WS: binary message of 3 bytes (base64): AP8Q"));
+}